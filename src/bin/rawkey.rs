@@ -0,0 +1,261 @@
+//! A command line tool to protect and recover secrets with rawkey
+//!
+//! The user secret is never accepted as command line argument since arguments are visible to other
+//! processes; it is read from a file, an inherited file descriptor or an environment variable.
+
+use kync_rawkey::{ Capsule, Config, RawKey, Secret, UserSecret };
+use std::{
+	env, fs, process,
+	io::{ self, Read, Write }
+};
+
+
+/// The usage string
+const USAGE: &str = "Usage:
+    rawkey seal [--config <config>] [--context <context>] [--in <file>] [--out <file>] <source>
+    rawkey open [--context <context>] [--in <file>] [--out <file>] <source>
+    rawkey inspect [--in <file>]
+    rawkey keygen [--format <raw|text>] [--bytes <count>] [--out <file>]
+
+User secret sources (exactly one is required for `seal` and `open`):
+    --secret-file <path>    Reads the user secret from a file
+    --secret-fd <fd>        Reads the user secret from an inherited file descriptor (unix only)
+    --secret-env <var>      Reads the user secret from an environment variable
+
+If `--in` or `--out` is omitted, stdin or stdout is used. The default config is
+`Blake2b-AES256GCMSIV`; `keygen` creates 32 random bytes by default (`--bytes` must be between 16
+and 32). `--format text` creates a grouped, checksummed Base58 string instead.";
+
+
+/// A command line error
+enum CliError {
+	/// The command line is invalid
+	Usage(String),
+	/// The operation failed
+	Failed(String)
+}
+impl CliError {
+	/// Creates a usage error
+	pub fn usage(message: impl ToString) -> Self {
+		Self::Usage(message.to_string())
+	}
+	/// Creates an operation error
+	pub fn failed(message: impl ToString) -> Self {
+		Self::Failed(message.to_string())
+	}
+}
+impl<E: ToString> From<E> for CliError {
+	fn from(error: E) -> Self {
+		Self::Failed(error.to_string())
+	}
+}
+
+
+/// The options accepted by `seal`
+const SEAL_OPTIONS: [&str; 7] =
+	["--config", "--context", "--in", "--out", "--secret-file", "--secret-fd", "--secret-env"];
+/// The options accepted by `open`
+const OPEN_OPTIONS: [&str; 6] =
+	["--context", "--in", "--out", "--secret-file", "--secret-fd", "--secret-env"];
+
+
+/// The parsed command line options
+#[derive(Default)]
+struct Options {
+	config: Option<String>,
+	context: Option<String>,
+	input: Option<String>,
+	output: Option<String>,
+	bytes: Option<String>,
+	format: Option<String>,
+	secret_file: Option<String>,
+	secret_fd: Option<String>,
+	secret_env: Option<String>
+}
+impl Options {
+	/// Parses the options from `args` and rejects every option that is not in `allowed`
+	pub fn parse(args: impl Iterator<Item = String>, allowed: &[&str]) -> Result<Self, CliError> {
+		let (mut this, mut args) = (Self::default(), args);
+		while let Some(arg) = args.next() {
+			let slot = match arg.as_str() {
+				"--config" => &mut this.config,
+				"--context" => &mut this.context,
+				"--in" => &mut this.input,
+				"--out" => &mut this.output,
+				"--bytes" => &mut this.bytes,
+				"--format" => &mut this.format,
+				"--secret-file" => &mut this.secret_file,
+				"--secret-fd" => &mut this.secret_fd,
+				"--secret-env" => &mut this.secret_env,
+				_ => Err(CliError::usage(format!("Unexpected argument: {}", arg)))?
+			};
+			if !allowed.contains(&arg.as_str()) {
+				Err(CliError::usage(format!("Option not supported by this command: {}", arg)))?
+			}
+			let value = args.next()
+				.ok_or_else(|| CliError::usage(format!("Missing value for {}", arg)))?;
+			if slot.replace(value).is_some() {
+				Err(CliError::usage(format!("Duplicate argument: {}", arg)))?
+			}
+		}
+		Ok(this)
+	}
+	
+	/// Creates the `RawKey` instance with the selected context
+	pub fn rawkey(&self) -> RawKey {
+		match self.context.as_ref() {
+			Some(context) => RawKey::with_context(context.as_bytes()),
+			None => RawKey::new()
+		}
+	}
+	/// Reads the user secret from the selected source
+	pub fn user_secret(&self) -> Result<Secret, CliError> {
+		let secret = match (&self.secret_file, &self.secret_fd, &self.secret_env) {
+			(Some(path), None, None) => fs::read(path)?,
+			(None, Some(fd), None) => read_fd(fd)?,
+			(None, None, Some(var)) => env::var(var)
+				.map_err(|e| CliError::failed(format!("Invalid variable {}: {}", var, e)))?
+				.into_bytes(),
+			_ => Err(CliError::usage("Exactly one user secret source is required"))?
+		};
+		Ok(Secret::from(secret))
+	}
+	/// Reads the input
+	pub fn read_input(&self) -> Result<Secret, CliError> {
+		let mut input = Vec::new();
+		match self.input.as_ref() {
+			Some(path) => input = fs::read(path)?,
+			None => { io::stdin().read_to_end(&mut input)?; }
+		}
+		Ok(Secret::from(input))
+	}
+	/// Writes `data` to the output
+	pub fn write_output(&self, data: &[u8]) -> Result<(), CliError> {
+		match self.output.as_ref() {
+			Some(path) => create_output(path)?.write_all(data)?,
+			None => io::stdout().write_all(data)?
+		}
+		Ok(())
+	}
+}
+
+
+/// Reads all data from the inherited file descriptor `fd`
+///
+/// The standard streams are rejected and the descriptor is never closed, so it cannot interfere
+/// with the input or output.
+#[cfg(unix)]
+fn read_fd(fd: &str) -> Result<Vec<u8>, CliError> {
+	use std::{ fs::File, mem::ManuallyDrop, os::unix::io::FromRawFd };
+	
+	// Validate the descriptor
+	let invalid = || CliError::usage(format!("Invalid file descriptor: {}", fd));
+	let fd: i32 = fd.parse().map_err(|_| invalid())?;
+	if fd <= 2 {
+		Err(invalid())?
+	}
+	if fs::metadata(format!("/dev/fd/{}", fd)).is_err() {
+		Err(CliError::failed(format!("File descriptor is not open: {}", fd)))?
+	}
+	
+	// Borrow the descriptor without taking ownership
+	let mut file = ManuallyDrop::new(unsafe{ File::from_raw_fd(fd) });
+	let mut secret = Vec::new();
+	file.read_to_end(&mut secret)?;
+	Ok(secret)
+}
+/// Reads all data from the inherited file descriptor `fd`
+#[cfg(not(unix))]
+fn read_fd(_fd: &str) -> Result<Vec<u8>, CliError> {
+	Err(CliError::usage("File descriptors are only supported on unix"))
+}
+
+/// Creates or truncates the output file at `path` (new files are only accessible by the owner since
+/// the output may be a user secret or a recovered secret)
+#[cfg(unix)]
+fn create_output(path: &str) -> Result<fs::File, CliError> {
+	use std::os::unix::fs::OpenOptionsExt;
+	Ok(fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?)
+}
+/// Creates or truncates the output file at `path`
+#[cfg(not(unix))]
+fn create_output(path: &str) -> Result<fs::File, CliError> {
+	Ok(fs::File::create(path)?)
+}
+
+
+/// Seals the input
+fn seal(options: Options) -> Result<(), CliError> {
+	let config = options.config.as_deref().unwrap_or("Blake2b-AES256GCMSIV");
+	let config = Config::from_name(config.as_bytes())
+		.ok_or_else(|| CliError::usage(format!("Unknown config: {}", config)))?;
+	
+	let (secret, auth) = (options.read_input()?, options.user_secret()?);
+	let capsule = options.rawkey().protect(&secret, &auth, config)?;
+	options.write_output(capsule.as_bytes())
+}
+
+/// Opens the input
+fn open(options: Options) -> Result<(), CliError> {
+	let (capsule, auth) = (options.read_input()?, options.user_secret()?);
+	let secret = options.rawkey().recover(&capsule, &auth)?;
+	options.write_output(&secret)
+}
+
+/// Prints the capsule metadata
+fn inspect(options: Options) -> Result<(), CliError> {
+	let capsule = Capsule::from(options.read_input()?.to_vec());
+	let info = capsule.info()?;
+	
+	let mut stdout = io::stdout();
+	for key_value in info.key_values() {
+		writeln!(stdout, "{}", key_value)?;
+	}
+	Ok(())
+}
+
+/// Generates a new random user secret
+fn keygen(options: Options) -> Result<(), CliError> {
+	let bytes = options.bytes.as_deref().unwrap_or("32");
+	let bits = bytes.parse::<u32>()
+		.map_err(|_| CliError::usage(format!("Invalid byte count: {}", bytes)))?
+		.saturating_mul(8);
+	
+	let secret = match options.format.as_deref() {
+		None | Some("raw") => UserSecret::generate_raw(bits)?,
+		Some("text") => UserSecret::generate(bits)?,
+		Some(format) => Err(CliError::usage(format!("Invalid format: {}", format)))?
+	};
+	options.write_output(&secret)
+}
+
+
+fn main() {
+	// Select the command
+	let mut args = env::args().skip(1);
+	let result = match args.next().as_deref() {
+		Some("seal") => Options::parse(args, &SEAL_OPTIONS).and_then(seal),
+		Some("open") => Options::parse(args, &OPEN_OPTIONS).and_then(open),
+		Some("inspect") => Options::parse(args, &["--in"]).and_then(inspect),
+		Some("keygen") => Options::parse(args, &["--format", "--bytes", "--out"]).and_then(keygen),
+		Some("help") | Some("--help") => {
+			println!("{}", USAGE);
+			Ok(())
+		},
+		Some(command) => Err(CliError::usage(format!("Unknown command: {}", command))),
+		None => Err(CliError::usage("Missing command"))
+	};
+	
+	// Handle the result
+	match result {
+		Ok(_) => (),
+		Err(CliError::Usage(e)) => {
+			eprintln!("{}\n\n{}", e, USAGE);
+			process::exit(2)
+		},
+		Err(CliError::Failed(e)) => {
+			eprintln!("{}", e);
+			process::exit(1)
+		}
+	}
+}
\ No newline at end of file
@@ -0,0 +1,232 @@
+use crate::{ crypto::{ Argon2Params, Config }, error::Error };
+
+
+/// The magic bytes that introduce a self-describing capsule
+const MAGIC: &[u8; 4] = b"RawK";
+/// The current capsule format version
+pub const VERSION: u8 = 1;
+/// The flag that indicates that the capsule is bound to an application specific context
+const FLAG_CONTEXT: u8 = 0x01;
+/// The flag that indicates that the capsule has multiple recipient slots
+const FLAG_MULTI_RECIPIENT: u8 = 0x02;
+/// The flag that indicates that the header contains a key identifier
+const FLAG_KEY_ID: u8 = 0x04;
+/// The flag that indicates that the secret is padded
+const FLAG_PADDED: u8 = 0x08;
+/// The length of a key identifier
+pub const KEY_ID_LEN: usize = 8;
+
+
+/// Public metadata about a capsule that can be obtained without the user secret
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CapsuleInfo {
+	/// The capsule format version (`0` for headerless legacy capsules)
+	pub version: u8,
+	/// The config used to create the capsule
+	pub config: Config,
+	/// Whether the capsule is bound to an application specific context or not
+	pub context: bool,
+	/// The Argon2id parameters (only set for password configs)
+	pub argon2: Option<Argon2Params>,
+	/// The amount of shares that are required to recover the secret (`1` for non-threshold
+	/// capsules)
+	pub threshold: usize,
+	/// The amount of recipient or share slots (`1` for single-recipient capsules)
+	pub recipients: usize,
+	/// The KDF salt (only set for single-recipient capsules since the slots of other capsules have
+	/// separate salts)
+	pub salt: Option<[u8; 16]>,
+	/// The identifier of the user secret (if the capsule contains one)
+	pub key_id: Option<[u8; KEY_ID_LEN]>,
+	/// Whether the secret is padded or not
+	pub padded: bool,
+	/// The length of the protected secret (including the padding if the secret is padded)
+	pub secret_len: usize
+}
+impl CapsuleInfo {
+	/// Formats the metadata as `key=value` pairs; the optional fields are omitted if they are not
+	/// set or `1`
+	pub fn key_values(&self) -> Vec<String> {
+		let mut key_values = vec![
+			format!("version={}", self.version),
+			format!("config={}", String::from_utf8_lossy(self.config.name())),
+			format!("context={}", self.context)
+		];
+		if let Some(argon2) = self.argon2 {
+			key_values.push(format!("argon2={},{},{}", argon2.m_cost, argon2.t_cost, argon2.p_cost));
+		}
+		if self.threshold > 1 {
+			key_values.push(format!("threshold={}", self.threshold));
+		}
+		if self.recipients > 1 {
+			key_values.push(format!("recipients={}", self.recipients));
+		}
+		if let Some(salt) = self.salt {
+			key_values.push(format!("salt={}", hex(&salt)));
+		}
+		if let Some(key_id) = self.key_id {
+			key_values.push(format!("key_id={}", hex(&key_id)));
+		}
+		if self.padded {
+			key_values.push("padded=true".to_string());
+		}
+		key_values.push(format!("secret_len={}", self.secret_len));
+		key_values
+	}
+}
+
+
+/// A capsule header
+///
+/// ## Format
+/// `magic[4] || version[1] || config_id[1] || flags[1] || argon2_params[0 or 12] || key_id[0 or 8]`
+///
+/// `flags` is a bitmask where `0x01` indicates that the capsule is bound to a context, `0x02`
+/// indicates that the capsule has multiple recipient slots, `0x04` indicates that a key identifier
+/// is present and `0x08` indicates that the secret is padded.
+/// `argon2_params` is only present for password configs and consists of the big-endian `u32`s
+/// `m_cost || t_cost || p_cost`.
+///
+/// Capsules without a header (i.e. capsules that don't start with `magic`) are legacy capsules that
+/// were created with the `Blake2b-ChaChaPolyIETF` config.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Header {
+	/// The config used to create the capsule
+	pub config: Config,
+	/// Whether the capsule is bound to an application specific context or not
+	pub context: bool,
+	/// The Argon2id parameters (must be set for password configs only)
+	pub argon2: Option<Argon2Params>,
+	/// Whether the capsule has multiple recipient slots or not
+	pub multi_recipient: bool,
+	/// The identifier of the user secret (if any)
+	pub key_id: Option<[u8; KEY_ID_LEN]>,
+	/// Whether the secret is padded or not
+	pub padded: bool
+}
+impl Header {
+	/// The length of the fixed header fields
+	pub const LEN: usize = MAGIC.len() + 3;
+	/// The length of the encoded Argon2id parameters
+	const ARGON2_LEN: usize = 12;
+	
+	/// Creates a new unpadded single-recipient header without key identifier for `config`, whether
+	/// a `context` is used or not and the `argon2` parameters
+	pub fn new(config: Config, context: bool, argon2: Option<Argon2Params>) -> Self {
+		Self { config, context, argon2, multi_recipient: false, key_id: None, padded: false }
+	}
+	
+	/// The encoded header length
+	pub fn encoded_len(&self) -> usize {
+		let argon2_len = if self.argon2.is_some() { Self::ARGON2_LEN } else { 0 };
+		let key_id_len = if self.key_id.is_some() { KEY_ID_LEN } else { 0 };
+		Self::LEN + argon2_len + key_id_len
+	}
+	/// Computes the encoded header length from the fixed header fields `fixed` (which must be
+	/// `Self::LEN` bytes long) so that the remaining header can be read from a stream
+	pub fn peek_len(fixed: &[u8]) -> usize {
+		let argon2_len = match Config::from_id(fixed[5]).map(Config::is_password) {
+			Some(true) => Self::ARGON2_LEN,
+			_ => 0
+		};
+		let key_id_len = if fixed[6] & FLAG_KEY_ID != 0 { KEY_ID_LEN } else { 0 };
+		Self::LEN + argon2_len + key_id_len
+	}
+	/// Encodes the header into `buf` (which must be `self.encoded_len()` bytes large)
+	pub fn encode(&self, buf: &mut[u8]) {
+		let (magic, buf) = buf.split_at_mut(MAGIC.len());
+		magic.copy_from_slice(MAGIC);
+		let mut flags = if self.context { FLAG_CONTEXT } else { 0 };
+		if self.multi_recipient {
+			flags |= FLAG_MULTI_RECIPIENT;
+		}
+		if self.key_id.is_some() {
+			flags |= FLAG_KEY_ID;
+		}
+		if self.padded {
+			flags |= FLAG_PADDED;
+		}
+		let (fields, buf) = buf.split_at_mut(3);
+		fields.copy_from_slice(&[VERSION, self.config.id(), flags]);
+		
+		// Encode the Argon2id parameters and the key identifier
+		let argon2_len = if self.argon2.is_some() { Self::ARGON2_LEN } else { 0 };
+		let (params_buf, key_id_buf) = buf.split_at_mut(argon2_len);
+		if let Some(argon2) = self.argon2 {
+			let params = [argon2.m_cost, argon2.t_cost, argon2.p_cost];
+			for (param, buf) in params.iter().zip(params_buf.chunks_exact_mut(4)) {
+				buf.copy_from_slice(&param.to_be_bytes());
+			}
+		}
+		if let Some(key_id) = self.key_id {
+			key_id_buf.copy_from_slice(&key_id);
+		}
+	}
+	/// Decodes the header from `capsule` and returns the header together with the remaining capsule
+	/// body
+	///
+	/// Returns `Ok(None)` if the capsule is a headerless legacy capsule
+	pub fn decode(capsule: &[u8]) -> Result<Option<(Self, &[u8])>, Error> {
+		// Check for the magic bytes
+		if !capsule.starts_with(MAGIC) {
+			return Ok(None)
+		}
+		if capsule.len() < Self::LEN {
+			Err(Error::Truncated)?
+		}
+		
+		// Parse the fields
+		let (header, body) = capsule.split_at(Self::LEN);
+		let (version, config_id, flags) = (header[4], header[5], header[6]);
+		if version != VERSION {
+			Err(Error::UnsupportedVersion)?
+		}
+		let config = Config::from_id(config_id).ok_or(Error::UnknownConfig)?;
+		if flags & !(FLAG_CONTEXT | FLAG_MULTI_RECIPIENT | FLAG_KEY_ID | FLAG_PADDED) != 0 {
+			Err(Error::UnsupportedFlags)?
+		}
+		if flags & FLAG_MULTI_RECIPIENT != 0 && config.is_password() {
+			// Each slot would run Argon2id, which multiplies the denial-of-service bound
+			Err(Error::UnsupportedFlags)?
+		}
+		
+		// Parse and validate the Argon2id parameters to reject denial-of-service capsules early
+		let (argon2, body) = match config.is_password() {
+			true if body.len() < Self::ARGON2_LEN => Err(Error::Truncated)?,
+			true => {
+				let (params, body) = body.split_at(Self::ARGON2_LEN);
+				let param = |i: usize| {
+					let mut bytes = [0; 4];
+					bytes.copy_from_slice(&params[i * 4 .. i * 4 + 4]);
+					u32::from_be_bytes(bytes)
+				};
+				let argon2 = Argon2Params { m_cost: param(0), t_cost: param(1), p_cost: param(2) };
+				argon2.validate()?;
+				(Some(argon2), body)
+			},
+			false => (None, body)
+		};
+		
+		// Parse the key identifier
+		let (key_id, body) = match flags & FLAG_KEY_ID != 0 {
+			true if body.len() < KEY_ID_LEN => Err(Error::Truncated)?,
+			true => {
+				let (encoded, body) = body.split_at(KEY_ID_LEN);
+				let mut key_id = [0; KEY_ID_LEN];
+				key_id.copy_from_slice(encoded);
+				(Some(key_id), body)
+			},
+			false => (None, body)
+		};
+		let (context, multi_recipient) =
+			(flags & FLAG_CONTEXT != 0, flags & FLAG_MULTI_RECIPIENT != 0);
+		let padded = flags & FLAG_PADDED != 0;
+		Ok(Some((Self { config, context, argon2, multi_recipient, key_id, padded }, body)))
+	}
+}
+
+
+/// Encodes `bytes` as lowercase hex
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
\ No newline at end of file
@@ -1,61 +1,1315 @@
-use crate::ffi::ResultLogExt;
-use std::os::raw::c_char;
+use crate::{
+	UID, error::Error, ffi::ResultLogExt, secret::Secret, shamir,
+	capsule::{ self, CapsuleInfo, Header, KEY_ID_LEN },
+	log::{ self, Level }
+};
 use crypto_api_osrandom::OsRandom;
 use crypto_api_blake2::Blake2b;
-use crypto_api_chachapoly::ChachaPolyIetf;
+use crypto_api_chachapoly::{ ChachaPolyIetf, XChachaPoly };
+use aes_gcm_siv::{ Aes256GcmSiv, aead::{ AeadInPlace, KeyInit } };
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+use hkdf::SimpleHkdf;
+use sha2::{ Sha512, Digest, digest::core_api::BlockSizeUser };
+use argon2::{ Algorithm, Argon2, AssociatedData, ParamsBuilder, Version };
+use std::{ error, mem::{ size_of, ManuallyDrop }, slice };
 
 
-const OVERHEAD: usize = 16 + 12 + 16;
+const SALT_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+const COMMITMENT_LEN: usize = 32;
+/// The length of the random data-encryption key of multi-recipient and threshold capsules
+const DEK_LEN: usize = 32;
+/// The length of the big-endian chunk counter and the final-chunk flag in a stream nonce
+const STREAM_NONCE_SUFFIX_LEN: usize = 5;
+/// The data length of each chunk of a stream capsule (except the final chunk which may be shorter)
+pub const STREAM_CHUNK_LEN: usize = 64 * 1024;
+/// The maximum amount of recipient slots (limits the amount of KDF invocations during recovery)
+pub const MAX_RECIPIENTS: usize = 16;
+/// The data that is authenticated with the AEAD key to create a key commitment
+const COMMITMENT_DOMAIN: &[u8] = b"de.KizzyCode.RawKey.KeyCommitment";
+/// The key that is used to hash the user secret into a key identifier
+const KEY_ID_DOMAIN: &[u8] = b"de.KizzyCode.RawKey.KeyId";
+/// The BLAKE3 `derive_key` context string
+const BLAKE3_CONTEXT: &str = "de.KizzyCode.RawKey.2C24B914-C9E9-41B3-8033-6B0364BCBA2E BLAKE3-KDF";
 
-const ERR_OSRANDOM: *const c_char = b"OsRandom failed to generate data\0".as_ptr().cast();
-const ERR_KDF: *const c_char = b"Blake2b-KDF failed to derive a key\0".as_ptr().cast();
-const ERR_SEAL: *const c_char = b"ChachaPolyIetf failed to seal some data\0".as_ptr().cast();
-const ERR_TRUNCATED: *const c_char = b"The capsule is truncated/damaged\0".as_ptr().cast();
-const ERR_OPEN: *const c_char = b"ChachaPolyIetf failed to open some data\0".as_ptr().cast();
+/// The slots and the payload of a multi-recipient or threshold capsule body
+type Slots<'a> = (Vec<&'a[u8]>, &'a[u8]);
 
 
-fn random(buf: &mut[u8]) -> Result<(), *const c_char> {
-	OsRandom::secure_rng().random(buf).log_map_err(ERR_OSRANDOM)
+/// A supported key derivation function
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Kdf {
+	/// The Blake2b-KDF with the base key as key and the info as personalization
+	Blake2b,
+	/// HKDF-SHA-512 (RFC 5869)
+	HkdfSha512,
+	/// BLAKE3 in `derive_key` mode with `salt || info || base_key` as key material
+	Blake3,
+	/// Argon2id with the info as associated data
+	Argon2id(Argon2Params)
 }
-fn kdf(base_key: &[u8], salt: &[u8]) -> Result<Vec<u8>, *const c_char> {
-	let mut buf = vec![0; 32];
-	Blake2b::kdf().derive(&mut buf, base_key, salt, b"")
-		.map(|_| buf).log_map_err(ERR_KDF)
+impl Kdf {
+	/// Derives a 32 byte key from `base_key`, the 16 byte `salt` and the 16 byte `info`
+	fn derive(self, base_key: &[u8], salt: &[u8], info: &[u8]) -> Result<Secret, Error> {
+		let mut buf = Secret::new(32);
+		let result = match self {
+			Self::Blake2b => Blake2b::kdf().derive(&mut buf, base_key, salt, info),
+			Self::HkdfSha512 => hkdf::<Sha512>(&mut buf, base_key, salt, info),
+			Self::Blake3 => {
+				// Wipe the hasher and the output reader since their state is derived from the key
+				let mut hasher = blake3::Hasher::new_derive_key(BLAKE3_CONTEXT);
+				let mut output = hasher.update(salt).update(info).update(base_key).finalize_xof();
+				output.fill(&mut buf);
+				hasher.zeroize();
+				output.zeroize();
+				Ok(())
+			},
+			Self::Argon2id(params) => argon2id(&mut buf, base_key, salt, info, params)
+		};
+		result.map(|_| buf).log_map_err(Error::Kdf)
+	}
 }
 
-pub fn protect(key: &[u8], data: &[u8]) -> Result<Vec<u8>, *const c_char> {
-	// Create and reference buffer
-	let mut capsule = vec![0; data.len() + OVERHEAD];
-	let (salt, buf) = capsule.split_at_mut(16);
-	let (nonce, buf) = buf.split_at_mut(12);
+
+/// The Argon2id cost parameters of a password capsule
+///
+/// The parameters are stored in the capsule header; to prevent denial-of-service attacks via crafted
+/// capsules, only parameters within `MIN..=MAX` are accepted.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Argon2Params {
+	/// The memory cost in KiB
+	pub m_cost: u32,
+	/// The amount of passes
+	pub t_cost: u32,
+	/// The degree of parallelism
+	pub p_cost: u32
+}
+impl Argon2Params {
+	/// The default parameters (the second recommended option from RFC 9106)
+	pub const DEFAULT: Self = Self { m_cost: 64 * 1024, t_cost: 3, p_cost: 4 };
+	/// The smallest accepted parameters
+	pub const MIN: Self = Self { m_cost: 8 * 1024, t_cost: 1, p_cost: 1 };
+	/// The largest accepted parameters
+	pub const MAX: Self = Self { m_cost: 1024 * 1024, t_cost: 16, p_cost: 16 };
+	
+	/// Ensures that the parameters are within `MIN..=MAX`
+	pub fn validate(self) -> Result<(), Error> {
+		let m_cost = Self::MIN.m_cost ..= Self::MAX.m_cost;
+		let t_cost = Self::MIN.t_cost ..= Self::MAX.t_cost;
+		let p_cost = Self::MIN.p_cost ..= Self::MAX.p_cost;
+		match m_cost.contains(&self.m_cost) && t_cost.contains(&self.t_cost)
+			&& p_cost.contains(&self.p_cost)
+		{
+			true => Ok(()),
+			false => Err(Error::InvalidKdfParams)
+		}
+	}
+}
+impl Default for Argon2Params {
+	fn default() -> Self {
+		Self::DEFAULT
+	}
+}
+
+
+/// A length-hiding padding that is applied to the secret before it is sealed
+///
+/// The padded secret is `secret || 0x80 || 0x00*` (ISO/IEC 7816-4), so the padding can be stripped
+/// without knowing the padding that was used; padded capsules are marked in the capsule header.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Padding {
+	/// No padding (the capsule length reveals the exact secret length)
+	None,
+	/// Pads the secret to the next power of two
+	///
+	/// Since the padding is at least one byte long, secrets whose length is a power of two are
+	/// padded to twice their length (e.g. AES-128 and AES-256 keys remain distinguishable); use a
+	/// bucket that is larger than all expected secrets to hide their length completely.
+	PowerOfTwo,
+	/// Pads the secret to the next multiple of the bucket length (which must be within
+	/// `1..=MAX_BUCKET_LEN`)
+	Bucket(usize)
+}
+impl Padding {
+	/// The default padding
+	pub const DEFAULT: Self = Self::None;
+	/// The largest accepted bucket length
+	pub const MAX_BUCKET_LEN: usize = 1024 * 1024;
+	
+	/// The padding that pads a secret to the padded length of the capsule described by `info` again
+	/// (padded capsules that exceed `MAX_BUCKET_LEN` are padded to the next power of two instead)
+	pub(crate) fn of(info: &CapsuleInfo) -> Self {
+		match info.padded {
+			true if info.secret_len <= Self::MAX_BUCKET_LEN => Self::Bucket(info.secret_len),
+			true => Self::PowerOfTwo,
+			false => Self::None
+		}
+	}
+	
+	/// Pads `data` (returns a copy of `data` if the padding is `None`)
+	fn pad(self, data: &[u8]) -> Result<Secret, Error> {
+		let len = match self {
+			Self::None => data.len(),
+			Self::PowerOfTwo => (data.len() + 1).checked_next_power_of_two()
+				.ok_or(Error::InvalidPadding)?,
+			Self::Bucket(bucket) if bucket == 0 || bucket > Self::MAX_BUCKET_LEN =>
+				Err(Error::InvalidPadding)?,
+			Self::Bucket(bucket) => (data.len() / bucket + 1).checked_mul(bucket)
+				.ok_or(Error::InvalidPadding)?
+		};
+		
+		let mut padded = Secret::new(len);
+		padded[..data.len()].copy_from_slice(data);
+		if self != Self::None {
+			padded[data.len()] = 0x80;
+		}
+		Ok(padded)
+	}
+}
+impl Default for Padding {
+	fn default() -> Self {
+		Self::DEFAULT
+	}
+}
+
+
+/// A supported AEAD cipher
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Aead {
+	/// ChachaPoly-IETF with a 12 byte nonce
+	ChachaPolyIetf,
+	/// XChachaPoly with a 24 byte nonce
+	XChachaPoly,
+	/// AES-256-GCM-SIV (RFC 8452) with a 12 byte nonce
+	Aes256GcmSiv
+}
+impl Aead {
+	/// The nonce length
+	fn nonce_len(self) -> usize {
+		match self {
+			Self::ChachaPolyIetf | Self::Aes256GcmSiv => 12,
+			Self::XChachaPoly => 24
+		}
+	}
+	/// Seals `plaintext` into `buf` (which must be `TAG_LEN` bytes larger) together with `ad`
+	fn seal(self, buf: &mut[u8], plaintext: &[u8], ad: &[u8], key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn error::Error>>
+	{
+		match self {
+			Self::ChachaPolyIetf =>
+				ChachaPolyIetf::aead_cipher().seal_to(buf, plaintext, ad, key, nonce),
+			Self::XChachaPoly =>
+				XChachaPoly::aead_cipher().seal_to(buf, plaintext, ad, key, nonce),
+			Self::Aes256GcmSiv => {
+				let (buf, tag) = buf.split_at_mut(plaintext.len());
+				buf.copy_from_slice(plaintext);
+				let computed = Aes256GcmSiv::new_from_slice(key)?
+					.encrypt_in_place_detached(nonce.into(), ad, buf)?;
+				tag.copy_from_slice(&computed);
+				Ok(plaintext.len() + TAG_LEN)
+			}
+		}
+	}
+	/// Opens `ciphertext` into `buf` together with `ad` and returns the plaintext length
+	fn open(self, buf: &mut[u8], ciphertext: &[u8], ad: &[u8], key: &[u8], nonce: &[u8])
+		-> Result<usize, Box<dyn error::Error>>
+	{
+		match self {
+			Self::ChachaPolyIetf =>
+				ChachaPolyIetf::aead_cipher().open_to(buf, ciphertext, ad, key, nonce),
+			Self::XChachaPoly =>
+				XChachaPoly::aead_cipher().open_to(buf, ciphertext, ad, key, nonce),
+			Self::Aes256GcmSiv => {
+				let len = ciphertext.len().checked_sub(TAG_LEN).ok_or("Ciphertext is truncated")?;
+				let (ciphertext, tag) = ciphertext.split_at(len);
+				buf[..len].copy_from_slice(ciphertext);
+				Aes256GcmSiv::new_from_slice(key)?
+					.decrypt_in_place_detached(nonce.into(), ad, &mut buf[..len], tag.into())?;
+				Ok(len)
+			}
+		}
+	}
+}
+
+/// A supported config
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Config {
+	/// Blake2b as KDF and ChachaPoly-IETF with a 12 byte nonce as AEAD cipher
+	Blake2bChachaPolyIetf,
+	/// Blake2b as KDF and XChachaPoly with a 24 byte nonce as AEAD cipher
+	Blake2bXChachaPoly,
+	/// Blake2b as KDF and the nonce-misuse resistant AES-256-GCM-SIV with a 12 byte nonce as AEAD
+	/// cipher
+	Blake2bAes256GcmSiv,
+	/// Like `Blake2bXChachaPoly` but with a key commitment, so that a capsule opens under exactly
+	/// one user secret
+	Blake2bXChachaPolyCommitting,
+	/// HKDF-SHA-512 as KDF and ChachaPoly-IETF with a 12 byte nonce as AEAD cipher
+	HkdfSha512ChachaPolyIetf,
+	/// BLAKE3 as KDF and ChachaPoly-IETF with a 12 byte nonce as AEAD cipher
+	Blake3ChachaPolyIetf,
+	/// Argon2id as password-stretching KDF and XChachaPoly with a 24 byte nonce as AEAD cipher; this
+	/// is the only config that can be used with passphrases
+	Argon2idXChachaPoly,
+	/// A threshold config that splits the secret into Shamir shares which are sealed using Blake2b
+	/// as KDF and ChachaPoly-IETF with a 12 byte nonce as AEAD cipher
+	Blake2bChachaPolyIetfShamir,
+	/// A stream config that seals the data in separate chunks using Blake2b as KDF and XChachaPoly
+	/// with a 24 byte nonce that is derived from a chunk counter as AEAD cipher
+	Blake2bXChachaPolyStream
+}
+impl Config {
+	/// All supported configs
+	pub const ALL: &'static [Self] = &[
+		Self::Blake2bChachaPolyIetf, Self::Blake2bXChachaPoly, Self::Blake2bAes256GcmSiv,
+		Self::Blake2bXChachaPolyCommitting, Self::HkdfSha512ChachaPolyIetf,
+		Self::Blake3ChachaPolyIetf, Self::Argon2idXChachaPoly, Self::Blake2bChachaPolyIetfShamir,
+		Self::Blake2bXChachaPolyStream
+	];
+	
+	/// Selects the config with the given name
+	pub fn from_name(name: &[u8]) -> Option<Self> {
+		Self::ALL.iter().copied().find(|c| c.name() == name)
+	}
+	/// Selects the config with the given capsule identifier
+	pub fn from_id(id: u8) -> Option<Self> {
+		Self::ALL.iter().copied().find(|c| c.id() == id)
+	}
+	/// The config name
+	pub fn name(self) -> &'static [u8] {
+		match self {
+			Self::Blake2bChachaPolyIetf => b"Blake2b-ChaChaPolyIETF",
+			Self::Blake2bXChachaPoly => b"Blake2b-XChaChaPoly",
+			Self::Blake2bAes256GcmSiv => b"Blake2b-AES256GCMSIV",
+			Self::Blake2bXChachaPolyCommitting => b"Blake2b-XChaChaPoly-Committing",
+			Self::HkdfSha512ChachaPolyIetf => b"HKDF-SHA512-ChaChaPolyIETF",
+			Self::Blake3ChachaPolyIetf => b"BLAKE3-ChaChaPolyIETF",
+			Self::Argon2idXChachaPoly => b"Argon2id-XChaChaPoly",
+			Self::Blake2bChachaPolyIetfShamir => b"Blake2b-ChaChaPolyIETF-Shamir",
+			Self::Blake2bXChachaPolyStream => b"Blake2b-XChaChaPoly-STREAM"
+		}
+	}
+	/// The stable identifier that is stored in the capsule header
+	pub fn id(self) -> u8 {
+		match self {
+			Self::Blake2bChachaPolyIetf => 0x01,
+			Self::Blake2bXChachaPoly => 0x02,
+			Self::Blake2bAes256GcmSiv => 0x03,
+			Self::Blake2bXChachaPolyCommitting => 0x04,
+			Self::HkdfSha512ChachaPolyIetf => 0x05,
+			Self::Blake3ChachaPolyIetf => 0x06,
+			Self::Argon2idXChachaPoly => 0x07,
+			Self::Blake2bChachaPolyIetfShamir => 0x08,
+			Self::Blake2bXChachaPolyStream => 0x09
+		}
+	}
+	/// Whether the config stretches the user secret so that a passphrase can be used or not
+	///
+	/// All other configs require a high-entropy user secret
+	pub fn is_password(self) -> bool {
+		self == Self::Argon2idXChachaPoly
+	}
+	/// Whether the config splits the secret into threshold shares or not
+	///
+	/// Creating a capsule with a single user secret creates a `1`-of-`1` share
+	pub fn is_threshold(self) -> bool {
+		self == Self::Blake2bChachaPolyIetfShamir
+	}
+	/// Whether the config seals the data in separate chunks that can be processed incrementally or
+	/// not
+	pub fn is_stream(self) -> bool {
+		self == Self::Blake2bXChachaPolyStream
+	}
+	
+	/// The KDF (password configs use `argon2` or the default parameters)
+	fn kdf(self, argon2: Option<Argon2Params>) -> Kdf {
+		match self {
+			Self::HkdfSha512ChachaPolyIetf => Kdf::HkdfSha512,
+			Self::Blake3ChachaPolyIetf => Kdf::Blake3,
+			Self::Argon2idXChachaPoly => Kdf::Argon2id(argon2.unwrap_or_default()),
+			_ => Kdf::Blake2b
+		}
+	}
+	/// The AEAD cipher
+	fn aead(self) -> Aead {
+		match self {
+			Self::Blake2bXChachaPoly | Self::Blake2bXChachaPolyCommitting
+				| Self::Argon2idXChachaPoly | Self::Blake2bXChachaPolyStream => Aead::XChachaPoly,
+			Self::Blake2bAes256GcmSiv => Aead::Aes256GcmSiv,
+			_ => Aead::ChachaPolyIetf
+		}
+	}
+	/// The length of the key commitment (or `0` if the config is not committing)
+	fn commitment_len(self) -> usize {
+		match self {
+			Self::Blake2bXChachaPolyCommitting => COMMITMENT_LEN,
+			_ => 0
+		}
+	}
+	/// The overhead of a capsule created with this config
+	fn overhead(self) -> usize {
+		SALT_LEN + self.aead().nonce_len() + self.commitment_len() + TAG_LEN
+	}
+}
+
+
+/// The values a capsule body is bound to
+///
+/// Legacy capsules are not bound to anything, so both fields are empty for them
+#[derive(Debug, Default)]
+struct Binding {
+	/// The 16 byte KDF info used for domain separation
+	info: Vec<u8>,
+	/// The AEAD associated data
+	ad: Vec<u8>,
+	/// Whether the binding includes an application specific context or not
+	context: bool
+}
+impl Binding {
+	/// Creates the binding for a capsule with `header`, `config`, `salt` and an optional `context`
+	///
+	/// ## Format
+	/// - `info = Blake2b-128(uid || config_name || context?)`
+	/// - `ad = header || salt || uid || config_name || context?`
+	///
+	/// Since the config name is fixed by the config ID in the header and the header also indicates
+	/// the presence of a context, the concatenation is unambiguous.
+	fn new(config: Config, header: &[u8], salt: &[u8], context: Option<&[u8]>)
+		-> Result<Self, Error>
+	{
+		let domain = [UID, config.name(), context.unwrap_or_default()].concat();
+		let mut info = vec![0; 16];
+		Blake2b::varlen_hash().varlen_hash(&mut info, &domain).log_map_err(Error::Hash)?;
+		
+		let ad = [header, salt, &domain].concat();
+		Ok(Self { info, ad, context: context.is_some() })
+	}
+}
+
+
+pub fn random(buf: &mut[u8]) -> Result<(), Error> {
+	OsRandom::secure_rng().random(buf).log_map_err(Error::Random)
+}
+/// Derives `buf.len()` bytes from `ikm` using HKDF with `H` as hash function
+fn hkdf<H: Digest + BlockSizeUser + Clone>(buf: &mut[u8], ikm: &[u8], salt: &[u8], info: &[u8])
+	-> Result<(), Box<dyn error::Error>>
+{
+	let (mut prk, hkdf) = SimpleHkdf::<H>::extract(Some(salt), ikm);
+	let mut hkdf = ManuallyDrop::new(hkdf);
+	let result = hkdf.expand(info, buf);
 	
-	// Generate salt, nonce and key
+	// Wipe the PRK and the HMAC state since they are derived from the key
+	prk.as_mut_slice().zeroize();
+	// The hash states are plain arrays without heap data or drop glue, so their memory can be
+	// overwritten once they are no longer used
+	let state = &mut *hkdf as *mut SimpleHkdf<H> as *mut u8;
+	unsafe{ slice::from_raw_parts_mut(state, size_of::<SimpleHkdf<H>>()) }.zeroize();
+	Ok(result?)
+}
+/// Derives `buf.len()` bytes from `password` using Argon2id with `info` as associated data
+fn argon2id(buf: &mut[u8], password: &[u8], salt: &[u8], info: &[u8], params: Argon2Params)
+	-> Result<(), Box<dyn error::Error>>
+{
+	let params = ParamsBuilder::new()
+		.m_cost(params.m_cost).t_cost(params.t_cost).p_cost(params.p_cost)
+		.data(AssociatedData::new(info)?).output_len(buf.len())
+		.build()?;
+	Argon2::new(Algorithm::Argon2id, Version::V0x13, params).hash_password_into(password, salt, buf)?;
+	Ok(())
+}
+/// The length of a recipient slot of a multi-recipient capsule or a share slot of a threshold
+/// capsule
+fn slot_len(config: Config) -> usize {
+	match config.is_threshold() {
+		true => 1 + DEK_LEN + config.overhead(),
+		false => DEK_LEN + config.overhead()
+	}
+}
+/// Splits a multi-recipient capsule body into its slots and the payload
+fn split_slots<'a>(header: &Header, body: &'a[u8]) -> Result<Slots<'a>, Error> {
+	let (slot_count, body) = body.split_first().ok_or(Error::Truncated)?;
+	let slot_count = *slot_count as usize;
+	if slot_count == 0 || slot_count > MAX_RECIPIENTS {
+		Err(Error::InvalidRecipients)?
+	}
+	
+	let slots_len = slot_count * slot_len(header.config);
+	if body.len() < slots_len + header.config.overhead() - SALT_LEN {
+		Err(Error::Truncated)?
+	}
+	let (slots, payload) = body.split_at(slots_len);
+	Ok((slots.chunks_exact(slot_len(header.config)).collect(), payload))
+}
+/// Splits a threshold capsule body into its threshold, share slots and the payload
+fn split_shares<'a>(header: &Header, body: &'a[u8]) -> Result<(usize, Slots<'a>), Error> {
+	let (threshold, body) = body.split_first().ok_or(Error::Truncated)?;
+	let (slots, payload) = split_slots(header, body)?;
+	if *threshold == 0 || *threshold as usize > slots.len() {
+		Err(Error::InvalidRecipients)?
+	}
+	Ok((*threshold as usize, (slots, payload)))
+}
+/// Selects the `context` for a capsule with `header`
+fn select_context<'a>(header: &Header, context: Option<&'a[u8]>) -> Result<Option<&'a[u8]>, Error> {
+	match (header.context, context) {
+		(false, _) => Ok(None),
+		(true, Some(context)) => Ok(Some(context)),
+		(true, None) => Err(Error::MissingContext)
+	}
+}
+/// Computes the key commitment `Blake2b-256-MAC(key, COMMITMENT_DOMAIN)` into `buf`
+fn commit(buf: &mut[u8], key: &[u8]) -> Result<(), Error> {
+	Blake2b::varlen_mac().varlen_auth(buf, COMMITMENT_DOMAIN, key)
+		.map(|_| ()).log_map_err(Error::Hash)
+}
+/// Ensures that the key identifier in `header` (if any) matches `key` before the KDF is invoked
+fn check_key_id(header: &Header, key: &[u8]) -> Result<(), Error> {
+	match header.key_id {
+		Some(expected) if !bool::from(key_id(key)?.ct_eq(&expected)) =>
+			Err("The key identifier does not match").log_map_err(Error::WrongKey),
+		_ => Ok(())
+	}
+}
+/// Strips the padding from the `secret` of a capsule with `header` (if it is padded) without
+/// branching on the secret bytes
+fn unpad(header: &Header, mut secret: Secret) -> Result<Secret, Error> {
+	if !header.padded {
+		return Ok(secret)
+	}
+	
+	// Find the position and value of the last non-zero byte
+	let (mut len, mut marker) = (0, 0);
+	for (pos, byte) in secret.iter().enumerate() {
+		let mask = 0usize.wrapping_sub((*byte != 0) as usize);
+		len = (pos & mask) | (len & !mask);
+		marker = (*byte as usize & mask) | (marker & !mask);
+	}
+	if marker != 0x80 {
+		Err(Error::InvalidPadding)?
+	}
+	secret.truncate(len);
+	Ok(secret)
+}
+/// Creates the nonce `nonce_prefix || counter[4] || final[1]` of a stream chunk
+fn stream_nonce(prefix: &[u8], counter: u32, last: bool) -> Vec<u8> {
+	[prefix, &counter.to_be_bytes(), &[last as u8]].concat()
+}
+/// Computes the data length of a stream capsule body
+fn stream_data_len(config: Config, body: &[u8]) -> Result<usize, Error> {
+	// All chunks except the final chunk are full; the final chunk is only empty if the data is empty
+	let prelude_len = SALT_LEN + config.aead().nonce_len() - STREAM_NONCE_SUFFIX_LEN;
+	let chunks_len = body.len().checked_sub(prelude_len + TAG_LEN).ok_or(Error::Truncated)?;
+	let full_chunks = chunks_len.saturating_sub(1) / (STREAM_CHUNK_LEN + TAG_LEN);
+	Ok(chunks_len - full_chunks * TAG_LEN)
+}
+/// Reads from `input` until `buf` is full or the input ends and returns the amount of bytes read
+fn fill(input: &mut impl FnMut(&mut[u8]) -> Result<usize, Error>, buf: &mut[u8])
+	-> Result<usize, Error>
+{
+	let mut filled = 0;
+	while filled < buf.len() {
+		match input(&mut buf[filled..])? {
+			0 => break,
+			read => filled += read
+		}
+	}
+	Ok(filled)
+}
+/// Creates a stream input that reads from `data`
+pub fn read_slice(mut data: &[u8]) -> impl FnMut(&mut[u8]) -> Result<usize, Error> + '_ {
+	move |buf| {
+		let len = buf.len().min(data.len());
+		buf[..len].copy_from_slice(&data[..len]);
+		data = &data[len..];
+		Ok(len)
+	}
+}
+
+
+/// Seals `data` into a new capsule using `config`, `key` and an optional application specific
+/// `context`
+///
+/// `argon2` is only used (and validated) if `config` is a password config; stream configs cannot be
+/// padded and fail with `InvalidPadding` if `padding` is set
+pub fn protect(config: Config, key: &[u8], context: Option<&[u8]>, argon2: Argon2Params,
+	padding: Padding, data: &[u8]) -> Result<Vec<u8>, Error>
+{
+	// Validate the parameters
+	if config.is_threshold() {
+		return protect_threshold(config, 1, &[key], context, padding, data)
+	}
+	if config.is_stream() {
+		if padding != Padding::None {
+			Err(Error::InvalidPadding)?
+		}
+		let mut capsule = Vec::new();
+		protect_stream(config, key, context, read_slice(data), |segment| {
+			capsule.extend_from_slice(segment);
+			Ok(())
+		})?;
+		return Ok(capsule)
+	}
+	let (argon2, key_id) = match config.is_password() {
+		true => (argon2.validate().map(|_| Some(argon2))?, None),
+		false => (None, Some(key_id(key)?))
+	};
+	let header = Header {
+		key_id, padded: padding != Padding::None,
+		..Header::new(config, context.is_some(), argon2)
+	};
+	let data = padding.pad(data)?;
+	
+	// Create the capsule and write the header (the capsule is a secret buffer until it is sealed
+	// because the AEAD cipher copies the plaintext into it first)
+	let mut capsule = Secret::new(header.encoded_len() + data.len() + config.overhead());
+	let (encoded, body) = capsule.split_at_mut(header.encoded_len());
+	header.encode(encoded);
+	
+	// Seal the body
+	seal(&header, key, context, &data, encoded, body)?;
+	Ok(capsule.into_vec())
+}
+
+/// Seals `data` into a new multi-recipient capsule that can be opened with any of `keys` using
+/// `config` and an optional application specific `context`
+///
+/// `data` is sealed with a random data-encryption key which is sealed into a slot for each key.
+/// Password configs are not supported since every slot would multiply the Argon2id cost of a
+/// recovery.
+///
+/// ## Format
+/// `header || slot_count[1] || slot[slot_count] || nonce[12 or 24] || commitment[0 or 32]
+///  || ciphertext* || tag[16]` where each `slot` is a regular capsule body that contains the
+/// data-encryption key
+pub fn protect_multi(config: Config, keys: &[&[u8]], context: Option<&[u8]>, padding: Padding,
+	data: &[u8]) -> Result<Vec<u8>, Error>
+{
+	// Validate the parameters
+	if config.is_password() || config.is_threshold() || config.is_stream() {
+		Err(Error::InvalidConfig)?
+	}
+	if keys.is_empty() || keys.len() > MAX_RECIPIENTS {
+		Err(Error::InvalidRecipients)?
+	}
+	let header = Header {
+		multi_recipient: true, padded: padding != Padding::None,
+		..Header::new(config, context.is_some(), None)
+	};
+	let data = padding.pad(data)?;
+	
+	// Create the capsule and write the header and slot count
+	let slots_len = keys.len() * slot_len(config);
+	let payload_len = config.overhead() - SALT_LEN + data.len();
+	let mut capsule = Secret::new(header.encoded_len() + 1 + slots_len + payload_len);
+	let (encoded, body) = capsule.split_at_mut(header.encoded_len());
+	header.encode(encoded);
+	let (slot_count, body) = body.split_at_mut(1);
+	slot_count[0] = keys.len() as u8;
+	
+	// Seal the data-encryption key into the slots and seal the payload
+	let mut dek = Secret::new(DEK_LEN);
+	random(&mut dek)?;
+	let (slots, payload) = body.split_at_mut(slots_len);
+	for (key, slot) in keys.iter().zip(slots.chunks_exact_mut(slot_len(config))) {
+		seal(&header, key, context, &dek, encoded, slot)?;
+	}
+	let binding = Binding::new(config, encoded, &[], context)?;
+	seal_keyed(config, &dek, &binding, &data, payload)?;
+	Ok(capsule.into_vec())
+}
+
+/// Splits `data` into shares so that it can be recovered with any `threshold` of `keys` using the
+/// threshold `config` and an optional application specific `context`
+///
+/// `data` is sealed with a random data-encryption key which is split into Shamir shares over GF(256)
+/// that are sealed into a slot for each (distinct) key.
+///
+/// ## Format
+/// `header || threshold[1] || slot_count[1] || slot[slot_count] || nonce[12] || ciphertext*
+///  || tag[16]` where each `slot` is a regular capsule body that contains a share
+pub fn protect_threshold(config: Config, threshold: usize, keys: &[&[u8]], context: Option<&[u8]>,
+	padding: Padding, data: &[u8]) -> Result<Vec<u8>, Error>
+{
+	// Validate the parameters
+	if !config.is_threshold() {
+		Err(Error::InvalidConfig)?
+	}
+	if keys.is_empty() || keys.len() > MAX_RECIPIENTS || threshold == 0 || threshold > keys.len() {
+		Err(Error::InvalidRecipients)?
+	}
+	
+	// Reject duplicate keys since a single key would open several shares
+	let key_ids = keys.iter().map(|key| key_id(key)).collect::<Result<Vec<_>, _>>()?;
+	if key_ids.iter().enumerate().any(|(pos, id)| key_ids[..pos].contains(id)) {
+		Err(Error::InvalidRecipients)?
+	}
+	let header = Header {
+		padded: padding != Padding::None,
+		..Header::new(config, context.is_some(), None)
+	};
+	let data = padding.pad(data)?;
+	
+	// Create the capsule and write the header, threshold and slot count
+	let slots_len = keys.len() * slot_len(config);
+	let payload_len = config.overhead() - SALT_LEN + data.len();
+	let mut capsule = Secret::new(header.encoded_len() + 2 + slots_len + payload_len);
+	let (encoded, body) = capsule.split_at_mut(header.encoded_len());
+	header.encode(encoded);
+	let (counts, body) = body.split_at_mut(2);
+	counts.copy_from_slice(&[threshold as u8, keys.len() as u8]);
+	
+	// Split the data-encryption key, seal the shares into the slots and seal the payload
+	let mut dek = Secret::new(DEK_LEN);
+	random(&mut dek)?;
+	let shares = shamir::split(&dek, threshold, keys.len())?;
+	let (slots, payload) = body.split_at_mut(slots_len);
+	let slots = slots.chunks_exact_mut(slot_len(config));
+	for ((key, share), slot) in keys.iter().zip(shares).zip(slots) {
+		seal(&header, key, context, &share, encoded, slot)?;
+	}
+	let binding = Binding::new(config, encoded, &[], context)?;
+	seal_keyed(config, &dek, &binding, &data, payload)?;
+	Ok(capsule.into_vec())
+}
+
+/// Seals the data read from `input` into a new stream capsule using the stream `config`, `key` and
+/// an optional application specific `context` and passes the capsule to `output` in segments
+///
+/// The data is split into chunks of `STREAM_CHUNK_LEN` bytes that are sealed separately, so neither
+/// the data nor the capsule are held in memory; each sealed chunk is passed as separate segment.
+///
+/// ## Format
+/// `header || salt[16] || nonce_prefix[19] || chunk*` where each `chunk` is `ciphertext* || tag[16]`
+/// and is sealed with the nonce `nonce_prefix || counter[4] || final[1]`. `counter` is the
+/// big-endian chunk index and `final` is `1` for the final chunk and `0` otherwise. All chunks
+/// except the final chunk contain `STREAM_CHUNK_LEN` bytes; the final chunk is only empty if the
+/// data is empty.
+pub fn protect_stream(config: Config, key: &[u8], context: Option<&[u8]>,
+	mut input: impl FnMut(&mut[u8]) -> Result<usize, Error>,
+	mut output: impl FnMut(&[u8]) -> Result<(), Error>) -> Result<(), Error>
+{
+	// Validate the parameters
+	if !config.is_stream() {
+		Err(Error::InvalidConfig)?
+	}
+	let key_id = Some(key_id(key)?);
+	let header = Header { key_id, ..Header::new(config, context.is_some(), None) };
+	
+	// Create the header, salt and nonce prefix and derive the key
+	let prefix_len = config.aead().nonce_len() - STREAM_NONCE_SUFFIX_LEN;
+	let mut prelude = vec![0; header.encoded_len() + SALT_LEN + prefix_len];
+	let (encoded, salt_prefix) = prelude.split_at_mut(header.encoded_len());
+	header.encode(encoded);
+	random(salt_prefix)?;
+	let salt = &salt_prefix[..SALT_LEN];
+	let binding = Binding::new(config, encoded, salt, context)?;
+	let key = config.kdf(None).derive(key, salt, &binding.info)?;
+	
+	// Read the first chunk before anything is written (one byte is read ahead to detect the final
+	// chunk)
+	let mut buf = Secret::new(STREAM_CHUNK_LEN + 1);
+	let mut filled = fill(&mut input, &mut buf)?;
+	output(&prelude)?;
+	
+	// Seal the chunks
+	let prefix = &prelude[prelude.len() - prefix_len..];
+	let mut sealed = vec![0; STREAM_CHUNK_LEN + TAG_LEN];
+	for counter in 0 ..= u32::MAX {
+		let (last, len) = (filled <= STREAM_CHUNK_LEN, filled.min(STREAM_CHUNK_LEN));
+		let nonce = stream_nonce(prefix, counter, last);
+		config.aead().seal(&mut sealed[..len + TAG_LEN], &buf[..len], &binding.ad, &key, &nonce)
+			.log_map_err(Error::Seal)?;
+		output(&sealed[..len + TAG_LEN])?;
+		
+		if last {
+			return Ok(())
+		}
+		buf[0] = buf[STREAM_CHUNK_LEN];
+		filled = 1 + fill(&mut input, &mut buf[1..])?;
+	}
+	Err("The data exceeds the maximum amount of chunks").log_map_err(Error::Seal)
+}
+
+/// Adds a slot for `new_key` to `capsule` using the existing recipient `key` and an optional
+/// application specific `context`
+///
+/// A single-recipient (or legacy) capsule is converted into a multi-recipient capsule with the
+/// slots `key` and `new_key`; password capsules cannot be converted.
+pub fn add_recipient(key: &[u8], new_key: &[u8], context: Option<&[u8]>, capsule: &[u8])
+	-> Result<Vec<u8>, Error>
+{
+	// Convert single-recipient capsules
+	let (header, body) = match Header::decode(capsule)? {
+		Some((header, body)) if header.multi_recipient => (header, body),
+		_ => {
+			// Pad the secret to the same length again
+			let info = inspect(capsule)?;
+			if info.config.is_password() {
+				Err(Error::InvalidConfig)?
+			}
+			// Keep the capsule bound to the same context as before
+			let secret = recover(key, context, capsule)?;
+			let context = context.filter(|_| info.context);
+			return protect_multi(info.config, &[key, new_key], context, Padding::of(&info), &secret)
+		}
+	};
+	let (encoded, (slots, payload)) =
+		(&capsule[..header.encoded_len()], split_slots(&header, body)?);
+	if slots.len() >= MAX_RECIPIENTS {
+		Err(Error::InvalidRecipients)?
+	}
+	
+	// Recover the data-encryption key and seal it into a new slot
+	let context = select_context(&header, context)?;
+	let dek = open_slots(&header, key, context, encoded, &slots)?;
+	let mut slot = Secret::new(slot_len(header.config));
+	seal(&header, new_key, context, &dek, encoded, &mut slot)?;
+	
+	let slot_count = [slots.len() as u8 + 1];
+	Ok([encoded, &slot_count, &slots.concat(), &slot, payload].concat())
+}
+
+/// Removes the slot at `index` from the multi-recipient `capsule`
+///
+/// To avoid locking out all recipients, `key` and the optional application specific `context` must
+/// open one of the remaining slots. Note that the data-encryption key is not changed; use a rewrap
+/// to revoke a recipient that may have kept a copy of the capsule.
+pub fn remove_recipient(key: &[u8], context: Option<&[u8]>, capsule: &[u8], index: usize)
+	-> Result<Vec<u8>, Error>
+{
+	// Decode the capsule and remove the slot
+	let (header, body) = match Header::decode(capsule)? {
+		Some((header, body)) if header.multi_recipient => (header, body),
+		_ => Err(Error::InvalidRecipients)?
+	};
+	let (encoded, (mut slots, payload)) =
+		(&capsule[..header.encoded_len()], split_slots(&header, body)?);
+	if index >= slots.len() || slots.len() == 1 {
+		Err(Error::InvalidRecipients)?
+	}
+	slots.remove(index);
+	
+	// Ensure that the remaining slots can still be opened
+	let context = select_context(&header, context)?;
+	open_slots(&header, key, context, encoded, &slots)?;
+	
+	let slot_count = [slots.len() as u8];
+	Ok([encoded, &slot_count, &slots.concat(), payload].concat())
+}
+
+/// Opens `capsule` using `key` and an optional application specific `context`
+///
+/// The `context` is only used if the capsule is bound to a context; however if the capsule is bound
+/// to a context and `context` is `None`, the recovery fails.
+///
+/// The config is taken from the capsule header; headerless legacy capsules are opened using the
+/// `Blake2b-ChaChaPolyIETF` config. Since a legacy capsule may start with the header magic bytes by
+/// chance, a capsule whose header cannot be parsed is also tried as legacy capsule before the
+/// original error is returned; capsules with a valid header are never retried.
+pub fn recover(key: &[u8], context: Option<&[u8]>, capsule: &[u8]) -> Result<Secret, Error> {
+	match Header::decode(capsule) {
+		Ok(Some((header, body))) => {
+			log::log(Level::Trace, format!("Decoded capsule header {:?}", header));
+			
+			// Select the context and open the capsule
+			let context = select_context(&header, context)?;
+			let encoded = &capsule[..header.encoded_len()];
+			let result = match header.multi_recipient {
+				true => open_multi(&header, key, context, encoded, body),
+				false if header.config.is_threshold() =>
+					open_threshold(&header, &[key], context, encoded, body),
+				false if header.config.is_stream() => open_stream(key, context, capsule),
+				false => open(&header, key, context, encoded, body)
+			};
+			result.and_then(|secret| unpad(&header, secret))
+		},
+		Ok(None) => {
+			log::log(Level::Info, "Recovering a legacy capsule without header");
+			open_legacy(key, capsule)
+		},
+		Err(e) => open_legacy_fallback(key, capsule).map_err(|_| e)
+	}
+}
+
+
+/// Opens the threshold `capsule` using `keys` and an optional application specific `context`
+///
+/// The capsule is recovered as soon as enough shares could be opened; the order of `keys` does not
+/// matter.
+pub fn recover_threshold(keys: &[&[u8]], context: Option<&[u8]>, capsule: &[u8])
+	-> Result<Secret, Error>
+{
+	let (header, body) = match Header::decode(capsule)? {
+		Some((header, body)) if header.config.is_threshold() => (header, body),
+		_ => Err(Error::InvalidConfig)?
+	};
+	let context = select_context(&header, context)?;
+	let secret = open_threshold(&header, keys, context, &capsule[..header.encoded_len()], body)?;
+	unpad(&header, secret)
+}
+
+
+/// Opens the stream capsule read from `input` using `key` and an optional application specific
+/// `context` and passes the data to `output` chunk by chunk
+///
+/// Each chunk is authenticated before it is passed to `output`; however since the data is passed on
+/// before the final chunk is reached, all output must be discarded if an error is returned. A
+/// capsule that ends before its final chunk fails with `Error::StreamTruncated`.
+pub fn recover_stream(key: &[u8], context: Option<&[u8]>,
+	mut input: impl FnMut(&mut[u8]) -> Result<usize, Error>,
+	mut output: impl FnMut(&[u8]) -> Result<(), Error>) -> Result<(), Error>
+{
+	// Read and decode the header
+	let mut encoded = vec![0; Header::LEN];
+	if fill(&mut input, &mut encoded)? < encoded.len() {
+		Err(Error::Truncated)?
+	}
+	encoded.resize(Header::peek_len(&encoded), 0);
+	if fill(&mut input, &mut encoded[Header::LEN..])? < encoded.len() - Header::LEN {
+		Err(Error::Truncated)?
+	}
+	let header = match Header::decode(&encoded)? {
+		Some((header, _)) if header.config.is_stream() => header,
+		_ => Err(Error::InvalidConfig)?
+	};
+	let (config, context) = (header.config, select_context(&header, context)?);
+	check_key_id(&header, key)?;
+	
+	// Read the salt and nonce prefix and derive the key
+	let mut prelude = vec![0; SALT_LEN + config.aead().nonce_len() - STREAM_NONCE_SUFFIX_LEN];
+	if fill(&mut input, &mut prelude)? < prelude.len() {
+		Err(Error::Truncated)?
+	}
+	let (salt, prefix) = prelude.split_at(SALT_LEN);
+	let binding = Binding::new(config, &encoded, salt, context)?;
+	let key = config.kdf(None).derive(key, salt, &binding.info)?;
+	let err = if binding.context { Error::ContextAuthentication } else { Error::Authentication };
+	
+	// Open the chunks (one byte is read ahead to detect the final chunk)
+	const SEALED_LEN: usize = STREAM_CHUNK_LEN + TAG_LEN;
+	let (mut buf, mut opened) = (vec![0; SEALED_LEN + 1], Secret::new(SEALED_LEN));
+	let mut filled = fill(&mut input, &mut buf)?;
+	for counter in 0 ..= u32::MAX {
+		let (last, chunk) = (filled <= SEALED_LEN, &buf[..filled.min(SEALED_LEN)]);
+		if chunk.len() < TAG_LEN {
+			Err(Error::Truncated)?
+		}
+		
+		// Open the chunk and check whether a final chunk is missing if it cannot be opened
+		let aead = config.aead();
+		let nonce = stream_nonce(prefix, counter, last);
+		let len = match aead.open(&mut opened, chunk, &binding.ad, &key, &nonce) {
+			Ok(len) => len,
+			Err(_) if last && aead.open(&mut opened, chunk, &binding.ad, &key,
+				&stream_nonce(prefix, counter, false)).is_ok() =>
+				Err("The capsule ends with a non-final chunk").log_map_err(Error::StreamTruncated)?,
+			Err(e) => Err(e).log_map_err(err)?
+		};
+		if last && len == 0 && counter > 0 {
+			Err("The capsule ends with an empty final chunk").log_map_err(err)?
+		}
+		output(&opened[..len])?;
+		
+		if last {
+			return Ok(())
+		}
+		buf[0] = buf[SEALED_LEN];
+		filled = 1 + fill(&mut input, &mut buf[1..])?;
+	}
+	Err("The capsule exceeds the maximum amount of chunks").log_map_err(err)
+}
+
+
+/// Computes the non-secret identifier `Blake2b-64-MAC(KEY_ID_DOMAIN, key)` of the user secret `key`
+///
+/// The identifier is stored in the header of capsules created with key configs so that a host can
+/// select the matching user secret and a wrong user secret is detected before the KDF is invoked.
+/// It is not stored for password configs since it would allow to test passphrases without Argon2id.
+pub fn key_id(key: &[u8]) -> Result<[u8; KEY_ID_LEN], Error> {
+	let mut key_id = [0; KEY_ID_LEN];
+	Blake2b::varlen_mac().varlen_auth(&mut key_id, key, KEY_ID_DOMAIN).log_map_err(Error::Hash)?;
+	Ok(key_id)
+}
+
+
+/// Parses the public metadata of `capsule`
+pub fn inspect(capsule: &[u8]) -> Result<CapsuleInfo, Error> {
+	// Parse the header
+	let (version, header, body) = match Header::decode(capsule)? {
+		Some((header, body)) => (capsule::VERSION, header, body),
+		None => (0, Header::new(Config::Blake2bChachaPolyIetf, false, None), capsule)
+	};
+	
+	// Compute the secret length
+	let payload_overhead = header.config.overhead() - SALT_LEN;
+	let (threshold, recipients, secret_len) = match header.multi_recipient {
+		true => {
+			let (slots, payload) = split_slots(&header, body)?;
+			(1, slots.len(), payload.len() - payload_overhead)
+		},
+		false if header.config.is_threshold() => {
+			let (threshold, (slots, payload)) = split_shares(&header, body)?;
+			(threshold, slots.len(), payload.len() - payload_overhead)
+		},
+		false if header.config.is_stream() => (1, 1, stream_data_len(header.config, body)?),
+		false => (1, 1, body.len().checked_sub(header.config.overhead()).ok_or(Error::Truncated)?)
+	};
+	
+	// Copy the salt of single-recipient capsules (the body length has been validated above)
+	let salt = match header.multi_recipient || header.config.is_threshold() {
+		true => None,
+		false => {
+			let mut salt = [0; SALT_LEN];
+			salt.copy_from_slice(&body[..SALT_LEN]);
+			Some(salt)
+		}
+	};
+	Ok(CapsuleInfo {
+		version, config: header.config, context: header.context, argon2: header.argon2, threshold,
+		recipients, salt, key_id: header.key_id, padded: header.padded, secret_len
+	})
+}
+
+
+/// Seals `data` into `buf` (which must be `data.len() + config.overhead()` bytes large) and binds it
+/// to the `encoded` header and `context`
+///
+/// ## Format
+/// `salt[16] || nonce[12 or 24] || commitment[0 or 32] || ciphertext* || tag[16]`
+fn seal(header: &Header, key: &[u8], context: Option<&[u8]>, data: &[u8], encoded: &[u8],
+	buf: &mut[u8]) -> Result<(), Error>
+{
+	let config = header.config;
+	
+	// Generate salt, binding and key
+	let (salt, buf) = buf.split_at_mut(SALT_LEN);
 	random(salt)?;
+	let binding = Binding::new(config, encoded, salt, context)?;
+	let key = config.kdf(header.argon2).derive(key, salt, &binding.info)?;
+	seal_keyed(config, &key, &binding, data, buf)
+}
+
+/// Seals `data` into `buf` (which must be `data.len() + config.overhead() - SALT_LEN` bytes large)
+/// using the AEAD `key` and `binding`
+///
+/// ## Format
+/// `nonce[12 or 24] || commitment[0 or 32] || ciphertext* || tag[16]`
+fn seal_keyed(config: Config, key: &[u8], binding: &Binding, data: &[u8], buf: &mut[u8])
+	-> Result<(), Error>
+{
+	// Reference buffer
+	let (nonce, buf) = buf.split_at_mut(config.aead().nonce_len());
+	let (commitment, buf) = buf.split_at_mut(config.commitment_len());
+	
+	// Generate nonce and commitment
 	random(nonce)?;
-	let key = kdf(key, &salt)?;
+	if !commitment.is_empty() {
+		commit(commitment, key)?;
+	}
 	
 	// Seal the data
-	ChachaPolyIetf::aead_cipher().seal_to(buf, data, b"", &key, nonce)
-		.map(|_| capsule).log_map_err(ERR_SEAL)
+	config.aead().seal(buf, data, &binding.ad, key, nonce)
+		.map(|_| ()).log_map_err(Error::Seal)
 }
 
-pub fn recover(key: &[u8], data: &[u8]) -> Result<Vec<u8>, *const c_char> {
-	// Ensure the minimum length
-	if data.len() < OVERHEAD {
-		Err(ERR_TRUNCATED)?
+/// Opens a capsule body that was sealed using `header` and bound to the `encoded` header and
+/// `context`
+fn open(header: &Header, key: &[u8], context: Option<&[u8]>, encoded: &[u8], data: &[u8])
+	-> Result<Secret, Error>
+{
+	// Ensure the minimum length and check the key identifier
+	let config = header.config;
+	if data.len() < config.overhead() {
+		Err(Error::Truncated)?
+	}
+	check_key_id(header, key)?;
+	
+	let binding = Binding::new(config, encoded, &data[..SALT_LEN], context)?;
+	open_bound(config, config.kdf(header.argon2), key, &binding, data)
+}
+
+/// Opens a multi-recipient capsule body that was sealed using `header` and bound to the `encoded`
+/// header and `context`
+fn open_multi(header: &Header, key: &[u8], context: Option<&[u8]>, encoded: &[u8], data: &[u8])
+	-> Result<Secret, Error>
+{
+	let (slots, payload) = split_slots(header, data)?;
+	let dek = open_slots(header, key, context, encoded, &slots)?;
+	
+	let binding = Binding::new(header.config, encoded, &[], context)?;
+	open_keyed(header.config, &dek, &binding, payload)
+}
+
+/// Opens the first slot that can be opened with `key` and returns the data-encryption key
+fn open_slots(header: &Header, key: &[u8], context: Option<&[u8]>, encoded: &[u8],
+	slots: &[&[u8]]) -> Result<Secret, Error>
+{
+	let mut result = Err(Error::InvalidRecipients);
+	for slot in slots {
+		result = open(header, key, context, encoded, slot);
+		if result.is_ok() {
+			break
+		}
+	}
+	result
+}
+
+/// Opens a threshold capsule body that was sealed using `header` and bound to the `encoded` header
+/// and `context` by opening the share slots with `keys`
+fn open_threshold(header: &Header, keys: &[&[u8]], context: Option<&[u8]>, encoded: &[u8],
+	data: &[u8]) -> Result<Secret, Error>
+{
+	// Open the share slots until the threshold is reached
+	let (threshold, (slots, payload)) = split_shares(header, data)?;
+	let (mut shares, mut last_err) = (Vec::new(), Error::InvalidRecipients);
+	for slot in slots {
+		for key in keys {
+			match open(header, key, context, encoded, slot) {
+				Ok(share) => {
+					shares.push(share);
+					break
+				},
+				Err(e) => last_err = e
+			}
+		}
+		if shares.len() == threshold {
+			break
+		}
+	}
+	
+	// Combine the shares (return the authentication error if no share could be opened at all)
+	match shares.len() {
+		0 => Err(last_err)?,
+		len if len < threshold => Err(Error::ThresholdNotMet)?,
+		_ => ()
 	}
+	let shares: Vec<&[u8]> = shares.iter().map(|share| share.as_ref()).collect();
+	let dek = shamir::combine(&shares);
 	
+	let binding = Binding::new(header.config, encoded, &[], context)?;
+	open_keyed(header.config, &dek, &binding, payload)
+}
+
+/// Opens the stream `capsule` into a single buffer
+fn open_stream(key: &[u8], context: Option<&[u8]>, capsule: &[u8]) -> Result<Secret, Error> {
+	// Preallocate the buffer so that the secret is never reallocated
+	let mut buf = Secret::new(inspect(capsule)?.secret_len);
+	let mut pos = 0;
+	recover_stream(key, context, read_slice(capsule), |data| {
+		buf[pos .. pos + data.len()].copy_from_slice(data);
+		pos += data.len();
+		Ok(())
+	})?;
+	Ok(buf)
+}
+
+/// Opens a headerless legacy capsule that is not bound to anything
+fn open_legacy(key: &[u8], data: &[u8]) -> Result<Secret, Error> {
+	// Ensure the minimum length
+	let config = Config::Blake2bChachaPolyIetf;
+	if data.len() < config.overhead() {
+		Err(Error::Truncated)?
+	}
+	open_bound(config, Kdf::Blake2b, key, &Binding::default(), data)
+}
+
+/// Tries to open a capsule that starts with the header magic bytes as legacy capsule
+fn open_legacy_fallback(key: &[u8], data: &[u8]) -> Result<Secret, Error> {
+	let secret = open_legacy(key, data)?;
+	log::log(Level::Warn, "Recovered a legacy capsule that starts with the header magic bytes");
+	Ok(secret)
+}
+
+/// Opens a capsule body with a length of at least `config.overhead()` using `kdf` and `binding`
+fn open_bound(config: Config, kdf: Kdf, key: &[u8], binding: &Binding, data: &[u8])
+	-> Result<Secret, Error>
+{
+	let (salt, data) = data.split_at(SALT_LEN);
+	let key = kdf.derive(key, salt, &binding.info)?;
+	open_keyed(config, &key, binding, data)
+}
+
+/// Opens `data` with a length of at least `config.overhead() - SALT_LEN` using the AEAD `key` and
+/// `binding`
+fn open_keyed(config: Config, key: &[u8], binding: &Binding, data: &[u8])
+	-> Result<Secret, Error>
+{
 	// Reference data and create buffer
-	let (salt, data) = data.split_at(16);
-	let (nonce, data) = data.split_at(12);
-	let mut buf = vec![0; data.len()];
+	let (nonce, data) = data.split_at(config.aead().nonce_len());
+	let (commitment, data) = data.split_at(config.commitment_len());
+	let mut buf = Secret::new(data.len());
+	
+	// Verify the commitment in constant time before the data is opened
+	let err = if binding.context { Error::ContextAuthentication } else { Error::Authentication };
+	if !commitment.is_empty() {
+		let mut expected = Secret::new(commitment.len());
+		commit(&mut expected, key)?;
+		if !bool::from(expected.ct_eq(commitment)) {
+			Err("The key commitment does not match").log_map_err(err)?
+		}
+	}
 	
-	// Generate key and open data
-	let key = kdf(key, &salt)?;
-	let len = ChachaPolyIetf::aead_cipher().open_to(&mut buf, data, b"", &key, nonce)
-		.log_map_err(ERR_OPEN)?;
+	// Open data
+	let len = config.aead().open(&mut buf, data, &binding.ad, key, nonce)
+		.log_map_err(err)?;
 	
 	// Truncate buffer
 	buf.truncate(len);
 	Ok(buf)
+}
+
+/// Tests AES-256-GCM-SIV against the known-answer tests from RFC 8452 (appendix C.2)
+#[test]
+fn test_aes256gcmsiv_kat() {
+	struct Vector {
+		key: &'static [u8],
+		nonce: &'static [u8],
+		ad: &'static [u8],
+		plaintext: &'static [u8],
+		/// `ciphertext || tag`
+		ciphertext: &'static [u8]
+	}
+	const VECTORS: &[Vector] = &[
+		Vector {
+			key: b"\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00",
+			nonce: b"\x03\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00",
+			ad: b"",
+			plaintext: b"",
+			ciphertext: b"\x07\xf5\xf4\x16\x9b\xbf\x55\xa8\x40\x0c\xd4\x7e\xa6\xfd\x40\x0f"
+		},
+		Vector {
+			key: b"\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00",
+			nonce: b"\x03\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00",
+			ad: b"",
+			plaintext: b"\x01\x00\x00\x00\x00\x00\x00\x00",
+			ciphertext: b"\xc2\xef\x32\x8e\x5c\x71\xc8\x3b\x84\x31\x22\x13\x0f\x73\x64\xb7\x61\xe0\xb9\x74\x27\xe3\xdf\x28"
+		},
+		Vector {
+			key: b"\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00",
+			nonce: b"\x03\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00",
+			ad: b"\x01",
+			plaintext: b"\x02\x00\x00\x00\x00\x00\x00\x00",
+			ciphertext: b"\x1d\xe2\x29\x67\x23\x7a\x81\x32\x91\x21\x3f\x26\x7e\x3b\x45\x2f\x02\xd0\x1a\xe3\x3e\x4e\xc8\x54"
+		},
+		Vector {
+			key: b"\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00",
+			nonce: b"\x03\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00",
+			ad: b"\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00",
+			plaintext: b"\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x03\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x04\x00\x00\x00\x00\x00\x00\x00",
+			ciphertext: b"\x4a\x01\x0a\x7e\x49\xb2\x0f\x5b\xd7\x02\xe3\xd4\x63\x7d\xed\x01\xb5\x9c\x4f\xf4\x03\xab\x4d\x21\x44\xc1\x69\x6f\x71\xf3\xa6\xf3\x11\x9f\x04\xe8\x27\x42\x8f\xa9\xeb\x28\x45\x70\x82\x11\x44\xe5\x5e\x35\x46\xfd\x46\x4a\x5a\xb0"
+		},
+		Vector {
+			key: b"\xe6\x60\x21\xd5\xeb\x8e\x4f\x40\x66\xd4\xad\xb9\xc3\x35\x60\xe4\xf4\x6e\x44\xbb\x3d\xa0\x01\x5c\x94\xf7\x08\x87\x36\x86\x42\x00",
+			nonce: b"\xe0\xea\xf5\x28\x4d\x88\x4a\x0e\x77\xd3\x16\x46",
+			ad: b"\x4f\xbd\xc6\x6f\x14",
+			plaintext: b"\x67\x1f\xdd",
+			ciphertext: b"\xdd\xf9\x09\xcb\x84\x26\x44\xb7\x34\x73\x25\x85\x45\x10\x83\x77\x80\xae\xd6"
+		}
+	];
+	
+	let aead = Aead::Aes256GcmSiv;
+	for Vector { key, nonce, ad, plaintext, ciphertext } in VECTORS {
+		// Seal and compare
+		let mut buf = vec![0; plaintext.len() + TAG_LEN];
+		assert_eq!(aead.seal(&mut buf, plaintext, ad, key, nonce).unwrap(), buf.len());
+		assert_eq!(&buf, ciphertext);
+		
+		// Open and compare
+		let mut buf = vec![0; ciphertext.len()];
+		let len = aead.open(&mut buf, ciphertext, ad, key, nonce).unwrap();
+		assert_eq!(&buf[..len], *plaintext);
+		
+		// Tamper with the tag
+		let mut tampered = ciphertext.to_vec();
+		*tampered.last_mut().unwrap() ^= 0x01;
+		assert!(aead.open(&mut buf, &tampered, ad, key, nonce).is_err());
+	}
+}
+
+
+/// Tests the HKDF construction against the SHA-256 test cases from RFC 5869 (appendix A.1 to A.3)
+#[test]
+fn test_hkdf_rfc5869() {
+	struct Vector {
+		ikm: &'static [u8],
+		salt: &'static [u8],
+		info: &'static [u8],
+		okm: &'static [u8]
+	}
+	const VECTORS: &[Vector] = &[
+		Vector {
+			ikm: b"\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b",
+			salt: b"\x00\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c",
+			info: b"\xf0\xf1\xf2\xf3\xf4\xf5\xf6\xf7\xf8\xf9",
+			okm: b"\x3c\xb2\x5f\x25\xfa\xac\xd5\x7a\x90\x43\x4f\x64\xd0\x36\x2f\x2a\x2d\x2d\x0a\x90\xcf\x1a\x5a\x4c\x5d\xb0\x2d\x56\xec\xc4\xc5\xbf\x34\x00\x72\x08\xd5\xb8\x87\x18\x58\x65"
+		},
+		Vector {
+			ikm: b"\x00\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x0e\x0f\x10\x11\x12\x13\x14\x15\x16\x17\x18\x19\x1a\x1b\x1c\x1d\x1e\x1f\x20\x21\x22\x23\x24\x25\x26\x27\x28\x29\x2a\x2b\x2c\x2d\x2e\x2f\x30\x31\x32\x33\x34\x35\x36\x37\x38\x39\x3a\x3b\x3c\x3d\x3e\x3f\x40\x41\x42\x43\x44\x45\x46\x47\x48\x49\x4a\x4b\x4c\x4d\x4e\x4f",
+			salt: b"\x60\x61\x62\x63\x64\x65\x66\x67\x68\x69\x6a\x6b\x6c\x6d\x6e\x6f\x70\x71\x72\x73\x74\x75\x76\x77\x78\x79\x7a\x7b\x7c\x7d\x7e\x7f\x80\x81\x82\x83\x84\x85\x86\x87\x88\x89\x8a\x8b\x8c\x8d\x8e\x8f\x90\x91\x92\x93\x94\x95\x96\x97\x98\x99\x9a\x9b\x9c\x9d\x9e\x9f\xa0\xa1\xa2\xa3\xa4\xa5\xa6\xa7\xa8\xa9\xaa\xab\xac\xad\xae\xaf",
+			info: b"\xb0\xb1\xb2\xb3\xb4\xb5\xb6\xb7\xb8\xb9\xba\xbb\xbc\xbd\xbe\xbf\xc0\xc1\xc2\xc3\xc4\xc5\xc6\xc7\xc8\xc9\xca\xcb\xcc\xcd\xce\xcf\xd0\xd1\xd2\xd3\xd4\xd5\xd6\xd7\xd8\xd9\xda\xdb\xdc\xdd\xde\xdf\xe0\xe1\xe2\xe3\xe4\xe5\xe6\xe7\xe8\xe9\xea\xeb\xec\xed\xee\xef\xf0\xf1\xf2\xf3\xf4\xf5\xf6\xf7\xf8\xf9\xfa\xfb\xfc\xfd\xfe\xff",
+			okm: b"\xb1\x1e\x39\x8d\xc8\x03\x27\xa1\xc8\xe7\xf7\x8c\x59\x6a\x49\x34\x4f\x01\x2e\xda\x2d\x4e\xfa\xd8\xa0\x50\xcc\x4c\x19\xaf\xa9\x7c\x59\x04\x5a\x99\xca\xc7\x82\x72\x71\xcb\x41\xc6\x5e\x59\x0e\x09\xda\x32\x75\x60\x0c\x2f\x09\xb8\x36\x77\x93\xa9\xac\xa3\xdb\x71\xcc\x30\xc5\x81\x79\xec\x3e\x87\xc1\x4c\x01\xd5\xc1\xf3\x43\x4f\x1d\x87"
+		},
+		Vector {
+			ikm: b"\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b",
+			salt: b"",
+			info: b"",
+			okm: b"\x8d\xa4\xe7\x75\xa5\x63\xc1\x8f\x71\x5f\x80\x2a\x06\x3c\x5a\x31\xb8\xa1\x1f\x5c\x5e\xe1\x87\x9e\xc3\x45\x4e\x5f\x3c\x73\x8d\x2d\x9d\x20\x13\x95\xfa\xa4\xb6\x1a\x96\xc8"
+		}
+	];
+	
+	for Vector { ikm, salt, info, okm } in VECTORS {
+		let mut buf = vec![0; okm.len()];
+		hkdf::<sha2::Sha256>(&mut buf, ikm, salt, info).unwrap();
+		assert_eq!(&buf, okm);
+	}
+}
+
+/// Tests the KDFs against vectors that were cross-checked with independent implementations
+///
+/// The HKDF-SHA512 vector was computed with the RFC 5869 construction on top of Python's `hmac`
+/// and `hashlib` modules. The BLAKE3 vector was computed in `derive_key` mode with a port of the
+/// BLAKE3 reference implementation (`reference_impl.rs` from the BLAKE3 repository).
+#[test]
+fn test_kdf() {
+	const BASE_KEY: &[u8] = b"Testolope";
+	const SALT: &[u8] = b"\x00\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x0e\x0f";
+	const INFO: &[u8] = b"\xf0\xf1\xf2\xf3\xf4\xf5\xf6\xf7\xf8\xf9\xfa\xfb\xfc\xfd\xfe\xff";
+	const VECTORS: &[(Kdf, &[u8])] = &[
+		(Kdf::HkdfSha512, b"\x6c\x03\x08\x69\x09\x25\x06\x15\xa4\x25\xe2\x4b\x25\x0c\xe5\x98\x5c\x35\x86\xf5\x61\x53\x5f\x55\x31\xd1\x9f\xd5\xf5\x1a\x68\x2c"),
+		(Kdf::Blake3, b"\x3a\x45\xb8\xdc\xd2\xb7\x3e\x6f\xf1\x29\x26\x3f\x80\x87\x7e\x47\x25\xa8\x56\xc2\x5a\x41\x84\xdc\x16\xa7\xc9\xf3\x69\xdb\xf7\xa2")
+	];
+	
+	for (kdf, key) in VECTORS {
+		assert_eq!(&*kdf.derive(BASE_KEY, SALT, INFO).unwrap(), *key);
+	}
+}
+
+/// Tests that a stream capsule which ends with an empty final chunk after a full chunk is rejected
+#[test]
+fn test_stream_empty_final_chunk() {
+	const KEY: &[u8] = b"oGKqY-Yx8wR-HFCMv-Y9Smh-N6oZb-p7ekX-tY3c5-ExCSY-vCG6c";
+	let config = Config::Blake2bXChachaPolyStream;
+	
+	// Seal a single full chunk that is not marked as final
+	let mut capsule = Vec::new();
+	let data = vec![0x2a; STREAM_CHUNK_LEN + 1];
+	protect_stream(config, KEY, None, read_slice(&data), |chunk| {
+		capsule.extend_from_slice(chunk);
+		Ok(())
+	}).unwrap();
+	capsule.truncate(capsule.len() - 1 - TAG_LEN);
+	
+	// Append an empty final chunk
+	let (header, body) = Header::decode(&capsule).unwrap().unwrap();
+	let encoded = &capsule[..header.encoded_len()];
+	let prefix_len = config.aead().nonce_len() - STREAM_NONCE_SUFFIX_LEN;
+	let (salt, prefix) = (&body[..SALT_LEN], &body[SALT_LEN .. SALT_LEN + prefix_len]);
+	let binding = Binding::new(config, encoded, salt, None).unwrap();
+	let key = config.kdf(None).derive(KEY, salt, &binding.info).unwrap();
+	let mut tag = vec![0; TAG_LEN];
+	config.aead().seal(&mut tag, b"", &binding.ad, &key, &stream_nonce(prefix, 1, true)).unwrap();
+	capsule.extend_from_slice(&tag);
+	
+	assert_eq!(recover(KEY, None, &capsule).unwrap_err(), Error::Authentication);
 }
\ No newline at end of file
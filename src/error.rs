@@ -0,0 +1,146 @@
+use std::{
+	error, ptr, os::raw::c_char,
+	fmt::{ self, Display, Formatter }
+};
+
+
+/// A rawkey error
+///
+/// Each error has a stable numeric code (see `Error::code`) and a static description which is also
+/// returned as error pointer by the C API. Codes are never changed or reused.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u32)]
+pub enum Error {
+	/// An unexpected `NULL` pointer was passed
+	NullPointer = 1,
+	/// The host's sink failed to accept a segment
+	SinkWrite = 2,
+	/// The requested API version is not supported
+	UnsupportedApi = 3,
+	/// The config is invalid/unsupported
+	InvalidConfig = 4,
+	/// The required authentication data is missing
+	MissingAuth = 5,
+	/// The OS' secure random number generator failed
+	Random = 6,
+	/// The key derivation failed
+	Kdf = 7,
+	/// The hash function failed
+	Hash = 8,
+	/// The AEAD cipher failed to seal the data
+	Seal = 9,
+	/// The capsule is truncated/damaged
+	Truncated = 10,
+	/// The capsule uses an unsupported format version
+	UnsupportedVersion = 11,
+	/// The capsule was created with an unknown config
+	UnknownConfig = 12,
+	/// The capsule uses unsupported flags
+	UnsupportedFlags = 13,
+	/// The capsule could not be authenticated (invalid user secret or damaged capsule)
+	Authentication = 14,
+	/// The context-bound capsule could not be authenticated (invalid user secret or context or
+	/// damaged capsule)
+	ContextAuthentication = 15,
+	/// The capsule is bound to a context but no context is set
+	MissingContext = 16,
+	/// The KDF parameters are out of bounds
+	InvalidKdfParams = 17,
+	/// The user secret does not satisfy the authentication policy
+	WeakAuth = 18,
+	/// The requested user secret strength is unsupported
+	InvalidSecretStrength = 19,
+	/// The encoded user secret is invalid (e.g. because of a typo)
+	InvalidUserSecret = 20,
+	/// The recipient count or slot index is invalid or the capsule is not a multi-recipient capsule
+	InvalidRecipients = 21,
+	/// Some but not enough shares of a threshold capsule could be opened
+	ThresholdNotMet = 22,
+	/// The stream capsule ends before its final chunk
+	StreamTruncated = 23,
+	/// The host's source failed to provide the input
+	SourceRead = 24,
+	/// The user secret does not match the key identifier of the capsule
+	WrongKey = 25,
+	/// The padding is invalid (e.g. an empty bucket or a padded capsule with a malformed padding)
+	InvalidPadding = 26
+}
+impl Error {
+	/// All errors
+	pub const ALL: &'static [Self] = &[
+		Self::NullPointer, Self::SinkWrite, Self::UnsupportedApi, Self::InvalidConfig,
+		Self::MissingAuth, Self::Random, Self::Kdf, Self::Hash, Self::Seal, Self::Truncated,
+		Self::UnsupportedVersion, Self::UnknownConfig, Self::UnsupportedFlags, Self::Authentication,
+		Self::ContextAuthentication, Self::MissingContext, Self::InvalidKdfParams, Self::WeakAuth,
+		Self::InvalidSecretStrength, Self::InvalidUserSecret, Self::InvalidRecipients,
+		Self::ThresholdNotMet, Self::StreamTruncated, Self::SourceRead, Self::WrongKey,
+		Self::InvalidPadding
+	];
+	/// The code for a `NULL` error pointer (i.e. success)
+	pub const CODE_OK: u32 = 0;
+	/// The code for an error pointer that does not belong to this library
+	pub const CODE_UNKNOWN: u32 = u32::MAX;
+	
+	/// The stable numeric error code
+	pub fn code(self) -> u32 {
+		self as u32
+	}
+	/// The error description
+	pub fn description(self) -> &'static str {
+		let description = self.c_description();
+		std::str::from_utf8(&description[..description.len() - 1]).unwrap_or("Unknown error")
+	}
+	
+	/// Gets the error that belongs to a static error description pointer returned by the C API
+	pub(crate) fn from_ptr(ptr: *const c_char) -> Option<Self> {
+		Self::ALL.iter().copied().find(|e| ptr::eq(e.as_ptr(), ptr))
+	}
+	/// A pointer to the static, `NUL`-terminated error description
+	pub(crate) fn as_ptr(self) -> *const c_char {
+		self.c_description().as_ptr().cast()
+	}
+	/// The static, `NUL`-terminated error description
+	fn c_description(self) -> &'static [u8] {
+		// The descriptions are statics to ensure that each pointer is unique
+		static DESCRIPTIONS: [&[u8]; 26] = [
+			b"Unexpected NULL pointer\0",
+			b"Failed to write to the sink\0",
+			b"Unsupported API version\0",
+			b"Invalid config\0",
+			b"Missing required authentication data\0",
+			b"OsRandom failed to generate data\0",
+			b"The KDF failed to derive a key\0",
+			b"Blake2b failed to compute a hash\0",
+			b"The AEAD cipher failed to seal some data\0",
+			b"The capsule is truncated/damaged\0",
+			b"Unsupported capsule format version\0",
+			b"The capsule was created with an unknown config\0",
+			b"The capsule uses unsupported flags\0",
+			b"The AEAD cipher failed to open some data\0",
+			b"The AEAD cipher failed to open some data (invalid user secret or context)\0",
+			b"The capsule is bound to a context but no context is set\0",
+			b"The KDF parameters are out of bounds\0",
+			b"The user secret is too short or has too little entropy\0",
+			b"Unsupported user secret strength\0",
+			b"The encoded user secret is invalid (mistyped?)\0",
+			b"Invalid recipient count/slot index or not a multi-recipient capsule\0",
+			b"Not enough shares could be opened to reach the threshold\0",
+			b"The stream capsule is truncated (the final chunk is missing)\0",
+			b"Failed to read from the source\0",
+			b"The user secret does not match the capsule's key identifier (wrong key)\0",
+			b"The padding is invalid\0"
+		];
+		DESCRIPTIONS[self.code() as usize - 1]
+	}
+}
+impl Display for Error {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		f.write_str(self.description())
+	}
+}
+impl error::Error for Error {}
+impl From<Error> for *const c_char {
+	fn from(error: Error) -> Self {
+		error.as_ptr()
+	}
+}
\ No newline at end of file
@@ -1,25 +1,59 @@
 #![allow(non_camel_case_types)]
-use crate::log;
-use std::{ slice, fmt::Display, os::raw::c_char };
+use crate::{ error::Error, log::{ self, Level } };
+use std::{ slice, cell::RefCell, ffi::CStr, fmt::Display, os::raw::c_char };
 
 
-/// An error string indicating a NULL pointer error
-const ERR_NULLPTR: *const c_char = b"Unexpected NULL pointer\n".as_ptr().cast();
+thread_local! {
+	/// The underlying cause of the current failure on this thread (if any)
+	static CAUSE: RefCell<Option<String>> = const { RefCell::new(None) };
+	/// The detailed description of the most recent failure on this thread
+	static LAST_ERROR_DETAIL: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+
+/// Records the underlying cause of the current failure on this thread
+fn set_cause(cause: String) {
+	CAUSE.with(|c| *c.borrow_mut() = Some(cause))
+}
+/// Clears the underlying cause of the current failure on this thread
+pub fn clear_cause() {
+	CAUSE.with(|c| *c.borrow_mut() = None)
+}
+/// Records `error` together with its underlying cause (if any) as most recent failure on this
+/// thread
+pub fn set_last_error(error: Error) {
+	let detail = match CAUSE.with(|c| c.borrow_mut().take()) {
+		Some(cause) => format!("{} ({})", error, cause),
+		None => error.to_string()
+	};
+	LAST_ERROR_DETAIL.with(|d| *d.borrow_mut() = Some(detail))
+}
+/// Gets the detailed description of the most recent failure on this thread
+pub fn last_error_detail() -> Option<String> {
+	LAST_ERROR_DETAIL.with(|d| d.borrow().clone())
+}
 
 
 /// Some `Result` extensions
 pub trait ResultLogExt<T, E: Display> {
 	/// Checks if a result contains an error and logs it
+	#[allow(unused)]
 	fn log_err(self) -> Result<T, E>;
-	/// Checks if a result contains an error, logs it and maps it afterwards
+	/// Checks if a result contains an error, logs it, records it as underlying cause for the
+	/// thread's last error detail and maps it afterwards
 	fn log_map_err<M>(self, m: M) -> Result<T, M>;
 }
 impl<T, E: Display> ResultLogExt<T, E> for Result<T, E> {
 	fn log_err(self) -> Result<T, E> {
-		self.map_err(|e| { log(e.to_string()); e })
+		self.inspect_err(|e| log::log(Level::Error, e.to_string()))
 	}
 	fn log_map_err<M>(self, m: M) -> Result<T, M> {
-		self.map_err(|e| { log(e.to_string()); m })
+		self.map_err(|e| {
+			let cause = e.to_string();
+			log::log(Level::Error, &cause);
+			set_cause(cause);
+			m
+		})
 	}
 }
 
@@ -42,12 +76,13 @@ impl StaticCharPtrExt for *const c_char {
 /// An extension to check and assign to a mutable pointer
 pub trait MutPtrExt<T: Copy> {
 	/// Checks and assigns a value to a `*mut T`
-	fn checked_set(self, v: T) -> Result<(), *const c_char>;
+	fn checked_set(self, v: T) -> Result<(), Error>;
 }
 impl<T: Copy> MutPtrExt<T> for *mut T {
-	fn checked_set(self, v: T) -> Result<(), *const c_char> {
-		let this = unsafe{ self.as_mut() }.ok_or(ERR_NULLPTR)?;
-		Ok(*this = v)
+	fn checked_set(self, v: T) -> Result<(), Error> {
+		let this = unsafe{ self.as_mut() }.ok_or(Error::NullPointer)?;
+		*this = v;
+		Ok(())
 	}
 }
 
@@ -62,15 +97,23 @@ pub mod sys {
 /// An extension to check and deref the slice type
 pub trait SliceTExt {
 	/// Checks and wraps a `*const sys::slice_t`
-	fn checked_slice<'a>(self) -> Result<&'a[u8], *const c_char>;
+	fn checked_slice<'a>(self) -> Result<&'a[u8], Error>;
+	/// Checks and wraps a `*const sys::slice_t` that points to an array of `len` slices
+	fn checked_slices<'a>(self, len: usize) -> Result<Vec<&'a[u8]>, Error>;
 }
 impl SliceTExt for *const sys::slice_t {
-	fn checked_slice<'a>(self) -> Result<&'a[u8], *const c_char> {
-		let this = unsafe{ self.as_ref() }.ok_or(ERR_NULLPTR)?;
+	fn checked_slice<'a>(self) -> Result<&'a[u8], Error> {
+		let this = unsafe{ self.as_ref() }.ok_or(Error::NullPointer)?;
 		match this.ptr.is_null() {
 			false => Ok(unsafe{ slice::from_raw_parts(this.ptr, this.len) }),
-			true => Err(ERR_NULLPTR)
+			true => Err(Error::NullPointer)
+		}
+	}
+	fn checked_slices<'a>(self, len: usize) -> Result<Vec<&'a[u8]>, Error> {
+		if self.is_null() {
+			Err(Error::NullPointer)?
 		}
+		(0 .. len).map(|i| unsafe{ self.add(i) }.checked_slice()).collect()
 	}
 }
 
@@ -78,17 +121,50 @@ impl SliceTExt for *const sys::slice_t {
 /// An extension to check and write to the write callback
 pub trait WriteTExt {
 	/// Checks and writes a segment to a `*const sys::write_t`
-	fn checked_write(self, data: impl AsRef<[u8]>) -> Result<(), *const c_char>;
+	///
+	/// If the sink fails, its error description is logged and `Error::SinkWrite` is returned
+	fn checked_write(self, data: impl AsRef<[u8]>) -> Result<(), Error>;
 }
 impl WriteTExt for *mut sys::write_t {
-	fn checked_write(self, data: impl AsRef<[u8]>) -> Result<(), *const c_char> {
+	fn checked_write(self, data: impl AsRef<[u8]>) -> Result<(), Error> {
 		let data = data.as_ref();
 		let slice = sys::slice_t{ ptr: data.as_ptr(), len: data.len() };
 		
-		let this = unsafe{ self.as_mut() }.ok_or(ERR_NULLPTR)?;
+		let this = unsafe{ self.as_mut() }.ok_or(Error::NullPointer)?;
+		let write = this.write.ok_or(Error::NullPointer)?;
 		match this.handle.is_null() {
-			false => unsafe{ this.write.unwrap()(this.handle, &slice) }.check(),
-			true => Err(ERR_NULLPTR)
+			false => unsafe{ write(this.handle, &slice) }.check()
+				.map_err(|e| unsafe{ CStr::from_ptr(e) }.to_string_lossy())
+				.log_map_err(Error::SinkWrite),
+			true => Err(Error::NullPointer)
+		}
+	}
+}
+
+
+/// An extension to check and read from the read callback
+pub trait ReadTExt {
+	/// Checks and reads up to `buf.len()` bytes from a `*mut sys::read_t` and returns the amount of
+	/// bytes read (`0` at the end of the input)
+	///
+	/// If the source fails, its error description is logged and `Error::SourceRead` is returned
+	fn checked_read(self, buf: &mut[u8]) -> Result<usize, Error>;
+}
+impl ReadTExt for *mut sys::read_t {
+	fn checked_read(self, buf: &mut[u8]) -> Result<usize, Error> {
+		let this = unsafe{ self.as_mut() }.ok_or(Error::NullPointer)?;
+		let read = this.read.ok_or(Error::NullPointer)?;
+		if this.handle.is_null() {
+			Err(Error::NullPointer)?
+		}
+		
+		let mut len = 0;
+		unsafe{ read(this.handle, buf.as_mut_ptr(), buf.len(), &mut len) }.check()
+			.map_err(|e| unsafe{ CStr::from_ptr(e) }.to_string_lossy())
+			.log_map_err(Error::SourceRead)?;
+		match len <= buf.len() {
+			true => Ok(len),
+			false => Err("The source read more bytes than requested").log_map_err(Error::SourceRead)
 		}
 	}
 }
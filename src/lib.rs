@@ -1,12 +1,26 @@
+//! A raw key plugin for KyNc which can also be used as a regular Rust library (see `RawKey`)
+
 //mod misc;
 mod ffi;
 mod crypto;
+mod capsule;
+mod secret;
+mod error;
+mod rawkey;
+mod shamir;
+mod policy;
+mod usersecret;
+mod log;
 
-use ffi::{ MutPtrExt, SliceTExt, WriteTExt, sys };
-use std::{
-	ptr, os::raw::c_char,
-	sync::atomic::{ AtomicU8, Ordering::SeqCst }
+pub use crate::{
+	capsule::CapsuleInfo, crypto::{ Argon2Params, Config, Padding }, error::Error,
+	policy::AuthPolicy, rawkey::{ Capsule, RawKey }, secret::Secret, usersecret::UserSecret
+};
+use crate::{
+	ffi::{ MutPtrExt, ReadTExt, SliceTExt, WriteTExt, sys },
+	log::Level
 };
+use std::{ ptr, convert::TryFrom, os::raw::c_char, sync::Mutex };
 
 
 // Use MAProper if the feature is enabled
@@ -15,42 +29,56 @@ use std::{
 static MA_PROPER: ma_proper::MAProper = ma_proper::MAProper;
 
 
-// Constants and global log level
+// Constants and global context
 const API: u16 = 0x01_00;
-const UID: &[u8] = b"de.KizzyCode.RawKey.2C24B914-C9E9-41B3-8033-6B0364BCBA2E";
-const CONFIG_BLAKE2B_CHACHAPOLY_IETF: &[u8] = b"Blake2b-ChaChaPolyIETF";
-static LOG_LEVEL: AtomicU8 = AtomicU8::new(0);
-
-
-const ERR_INVALID_API: *const c_char = b"Unsupported API version\0".as_ptr().cast();
-const ERR_INVALID_CONFIG: *const c_char = b"Invalid config\0".as_ptr().cast();
-const ERR_MISSING_AUTH: *const c_char = b"Missing required authentication data".as_ptr().cast();
+pub(crate) const UID: &[u8] = b"de.KizzyCode.RawKey.2C24B914-C9E9-41B3-8033-6B0364BCBA2E";
+static CONTEXT: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+static AUTH_POLICY: Mutex<AuthPolicy> = Mutex::new(AuthPolicy::DEFAULT);
+static PADDING: Mutex<Padding> = Mutex::new(Padding::DEFAULT);
 
 
-/// Logs some text
-#[allow(unused)]
-fn log(s: impl AsRef<str>) {
-	if LOG_LEVEL.load(SeqCst) > 0 {
-		eprintln!("{}", s.as_ref())
-	}
+/// Creates a `RawKey` instance with the current application specific context, auth policy and
+/// padding
+fn rawkey() -> RawKey {
+	let mut rawkey = match CONTEXT.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+		Some(context) => RawKey::with_context(context.as_slice()),
+		None => RawKey::new()
+	};
+	rawkey.set_auth_policy(*AUTH_POLICY.lock().unwrap_or_else(|e| e.into_inner()));
+	rawkey.set_padding(*PADDING.lock().unwrap_or_else(|e| e.into_inner()));
+	rawkey
 }
 
-/// Converts a `Result<(), *const c_char>>` to a nullable error pointer
-fn try_catch(f: impl FnOnce() -> Result<(), *const c_char>) -> *const c_char {
-	f().err().unwrap_or(ptr::null())
+/// Converts a `Result<(), Error>>` to a nullable error pointer and records the error as the
+/// thread's last error
+fn try_catch(f: impl FnOnce() -> Result<(), Error>) -> *const c_char {
+	ffi::clear_cause();
+	match f() {
+		Ok(_) => ptr::null(),
+		Err(e) => {
+			ffi::set_last_error(e);
+			e.as_ptr()
+		}
+	}
 }
 
 
 /// Initializes the library with a specific API version and a logging level
 ///
+/// `log_level` is `0` to disable logging or `1` (error), `2` (warn), `3` (info), `4` (debug) or `5`
+/// (trace); higher values are treated like `5`
+///
 /// Returns `NULL` on success or a pointer to a static error description
 #[no_mangle]
 pub extern "C" fn init(api: u16, log_level: u8) -> *const c_char {
-	LOG_LEVEL.store(log_level, SeqCst);
-	match api {
-		API => ptr::null(),
-		_ => ERR_INVALID_API
-	}
+	log::set_level(log_level);
+	try_catch(|| match api {
+		API => {
+			log::log(Level::Info, format!("Initialized rawkey with API version {:#06x}", api));
+			Ok(())
+		},
+		_ => Err(Error::UnsupportedApi)
+	})
 }
 
 
@@ -68,36 +96,111 @@ pub extern "C" fn id(sink: *mut sys::write_t) -> *const c_char {
 /// Returns `NULL` on success or a pointer to a static error description
 #[no_mangle]
 pub extern "C" fn configs(sink: *mut sys::write_t) -> *const c_char {
-	try_catch(|| sink.checked_write(CONFIG_BLAKE2B_CHACHAPOLY_IETF))
+	try_catch(|| {
+		for config in Config::ALL {
+			sink.checked_write(config.name())?;
+		}
+		Ok(())
+	})
+}
+
+
+/// Sets a host provided sink that receives each log line as separate segment instead of stderr
+///
+/// Passing `NULL` restores logging to stderr. The sink may be called from any thread that calls into
+/// this library and must remain valid until it is replaced.
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn set_log_sink(sink: *const sys::write_t) -> *const c_char {
+	try_catch(|| match log::set_sink(sink) {
+		true => Ok(()),
+		false => Err(Error::NullPointer)
+	})
 }
 
 
 /// Sets an optional application specific context if supported (useful to name the keys better etc.)
 ///
+/// The context is process wide and bound into every capsule that is created afterwards; such a
+/// capsule can only be recovered if the same context is set. An empty context unsets the context.
+///
 /// Returns `NULL` on success/if unsupported or a pointer to a static error description if a context
 /// is supported by the plugin but could not be set
 #[no_mangle]
-pub extern "C" fn set_context(_context: *const sys::slice_t) -> *const c_char {
-	ptr::null()
+pub extern "C" fn set_context(context: *const sys::slice_t) -> *const c_char {
+	try_catch(|| {
+		let context = context.checked_slice()?;
+		*CONTEXT.lock().unwrap_or_else(|e| e.into_inner()) = match context.is_empty() {
+			true => None,
+			false => Some(context.to_vec())
+		};
+		Ok(())
+	})
+}
+
+
+/// Sets the process wide policy that the user secret must satisfy to protect a secret with a key
+/// config
+///
+/// `min_len` is the minimum length of the user secret in bytes (default: `16`); if `check_entropy`
+/// is not `0`, obviously low-entropy user secrets (short repeated patterns or short lowercase
+/// strings) are rejected (default: `1`). Use `set_auth_policy(0, 0)` to accept legacy user secrets.
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn set_auth_policy(min_len: u64, check_entropy: u8) -> *const c_char {
+	try_catch(|| {
+		let min_len = usize::try_from(min_len).unwrap_or(usize::MAX);
+		let policy = AuthPolicy { min_len, check_entropy: check_entropy != 0 };
+		*AUTH_POLICY.lock().unwrap_or_else(|e| e.into_inner()) = policy;
+		Ok(())
+	})
+}
+
+
+/// Sets the process wide length-hiding padding that is applied to the secret of new capsules
+///
+/// `mode` is `0` for no padding (default), `1` to pad the secret to the next power of two or `2` to
+/// pad it to the next multiple of `bucket_len` (at most 1 MiB; ignored otherwise). Mode `1` does not
+/// hide secret lengths that are powers of two since they are padded to twice their length. Stream
+/// configs cannot be padded and fail if a padding is set; the padding is recorded in the capsule and
+/// stripped by `recover`.
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn set_padding(mode: u8, bucket_len: u64) -> *const c_char {
+	try_catch(|| {
+		let padding = match mode {
+			0 => Padding::None,
+			1 => Padding::PowerOfTwo,
+			2 if (1 ..= Padding::MAX_BUCKET_LEN as u64).contains(&bucket_len) =>
+				Padding::Bucket(bucket_len as usize),
+			_ => Err(Error::InvalidPadding)?
+		};
+		*PADDING.lock().unwrap_or_else(|e| e.into_inner()) = padding;
+		Ok(())
+	})
 }
 
 
 /// Queries the authentication requirements to protect a secret for a specific config
 ///
+/// The signature is fixed by the kync plugin API and has no out-parameter for the mode, so hosts
+/// must call `auth_info_mode` to learn whether the config accepts a passphrase.
+///
 /// Returns `NULL` on success or a pointer to a static error description
 #[no_mangle]
-extern "C" fn auth_info_protect(is_required: *mut u8, retries: *mut u64,
+pub extern "C" fn auth_info_protect(is_required: *mut u8, retries: *mut u64,
 	config: *const sys::slice_t) -> *const c_char
 {
 	try_catch(|| {
 		// Validate the passed config
-		if config.checked_slice()? != CONFIG_BLAKE2B_CHACHAPOLY_IETF {
-			Err(ERR_INVALID_CONFIG)?
-		}
+		Config::from_name(config.checked_slice()?).ok_or(Error::InvalidConfig)?;
 		
 		// Set info
 		is_required.checked_set(1)?;
-		retries.checked_set(u64::max_value())?;
+		retries.checked_set(u64::MAX)?;
 		Ok(())
 	})
 }
@@ -107,65 +210,416 @@ extern "C" fn auth_info_protect(is_required: *mut u8, retries: *mut u64,
 ///
 /// Returns `NULL` on success or a pointer to a static error description
 #[no_mangle]
-extern "C" fn auth_info_recover(is_required: *mut u8, retries: *mut u64,
+pub extern "C" fn auth_info_recover(is_required: *mut u8, retries: *mut u64,
 	config: *const sys::slice_t) -> *const c_char
 {
 	try_catch(|| {
 		// Validate the passed config
-		if config.checked_slice()? != CONFIG_BLAKE2B_CHACHAPOLY_IETF {
-			Err(ERR_INVALID_CONFIG)?
-		}
+		Config::from_name(config.checked_slice()?).ok_or(Error::InvalidConfig)?;
 		
 		// Set info
 		is_required.checked_set(1)?;
-		retries.checked_set(u64::max_value())?;
+		retries.checked_set(u64::MAX)?;
 		Ok(())
 	})
 }
 
 
+/// Queries the kind of user secret that is expected for a specific config; `mode` is set to `0` if
+/// a high-entropy key is required or to `1` if the user secret is stretched with Argon2id so that a
+/// passphrase can be used
+///
+/// This extends `auth_info_protect` and `auth_info_recover`, which cannot report the mode.
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn auth_info_mode(mode: *mut u8, config: *const sys::slice_t) -> *const c_char {
+	try_catch(|| {
+		let config = Config::from_name(config.checked_slice()?).ok_or(Error::InvalidConfig)?;
+		mode.checked_set(config.is_password() as u8)
+	})
+}
+
+
 /// Protects some data
 ///
 /// ## Algorithm
-/// 1. Create a secure random 16 byte KDF `salt` and a secure random 12 byte ChachaPoly `nonce`
-/// 2. Derive a ChachaPoly `aead_key` by using Blake2b as KDF with the `user_secret` as key and
-///    `salt` as salt
-/// 3. Seal `key` using ChachaPoly with `aead_key` as key and `nonce` as nonce
+/// 1. Create a secure random 16 byte KDF `salt` and a secure random ChachaPoly `nonce` (12 bytes
+///    for ChachaPoly-IETF, 24 bytes for XChachaPoly)
+/// 2. Derive a ChachaPoly `aead_key` by using Blake2b as KDF with the `user_secret` as key, `salt`
+///    as salt and `Blake2b-128(uid || config_name || context?)` as info
+/// 3. Seal `key` using ChachaPoly with `aead_key` as key, `nonce` as nonce and
+///    `header || salt || uid || config_name || context?` as associated data
+///
+/// (`context?` is the application specific context if one is set)
 ///
 /// ## Format
-/// `salt[16] || nonce[12] || chacha_ciphertext* || poly_tag[16]`
+/// `magic[4] || version[1] || config_id[1] || flags[1] || key_id[8] || salt[16]
+///  || nonce[12 or 24] || chacha_ciphertext* || poly_tag[16]`
 ///
 /// (`||` denotes concatenation)
+///
+/// Stream configs seal the data in chunks of 64 KiB and write each sealed chunk as separate segment
 #[no_mangle]
-extern "C" fn protect(sink: *mut sys::write_t, data: *const sys::slice_t,
+pub extern "C" fn protect(sink: *mut sys::write_t, data: *const sys::slice_t,
 	config: *const sys::slice_t, auth: *const sys::slice_t) -> *const c_char
 {
 	try_catch(|| {
 		// Validate the passed config
-		if config.checked_slice()? != CONFIG_BLAKE2B_CHACHAPOLY_IETF {
-			Err(ERR_INVALID_CONFIG)?
-		}
+		let config = Config::from_name(config.checked_slice()?).ok_or(Error::InvalidConfig)?;
 		
 		// Protect the key
-		let auth = auth.checked_slice().map_err(|_| ERR_MISSING_AUTH)?;
-		let protected = crypto::protect(auth, data.checked_slice()?)?;
-		Ok(sink.checked_write(&protected)?)
+		let auth = auth.checked_slice().map_err(|_| Error::MissingAuth)?;
+		let data = data.checked_slice()?;
+		log::log(Level::Debug, format!("Protecting {} bytes using {:?}", data.len(), config));
+		if config.is_stream() {
+			let input = crypto::read_slice(data);
+			return rawkey().protect_stream(input, auth, config, |segment| sink.checked_write(segment))
+		}
+		let protected = rawkey().protect(data, auth, config)?;
+		sink.checked_write(&protected)
 	})
 }
 
 
 /// Recovers some data
 ///
+/// The config is taken from the capsule header; headerless legacy capsules are recovered using the
+/// `Blake2b-ChaChaPolyIETF` config
+///
+/// Stream capsules are recovered chunk by chunk and each authenticated chunk is written as separate
+/// segment; if an error is returned, all segments written so far must be discarded.
+///
 /// Returns `NULL` on success or a pointer to a static error description
 #[no_mangle]
-extern "C" fn recover(sink: *mut sys::write_t, data: *const sys::slice_t, auth: *const sys::slice_t)
+pub extern "C" fn recover(sink: *mut sys::write_t, data: *const sys::slice_t, auth: *const sys::slice_t)
 	-> *const c_char
 {
 	try_catch(|| {
 		// Recover the key
-		let auth = auth.checked_slice().map_err(|_| ERR_MISSING_AUTH)?;
-		let recovered = crypto::recover(auth, data.checked_slice()?)?;
-		Ok(sink.checked_write(&recovered)?)
+		let auth = auth.checked_slice().map_err(|_| Error::MissingAuth)?;
+		let data = data.checked_slice()?;
+		log::log(Level::Debug, format!("Recovering a {} byte capsule", data.len()));
+		if crypto::inspect(data).map(|info| info.config.is_stream()).unwrap_or(false) {
+			let input = crypto::read_slice(data);
+			return rawkey().recover_stream(input, auth, |chunk| sink.checked_write(chunk))
+		}
+		let recovered = rawkey().recover(data, auth)?;
+		sink.checked_write(&recovered)
+	})
+}
+
+
+/// Computes the 8 byte identifier of the user secret `auth` and writes it to `sink`
+///
+/// Capsules created with a key config store this identifier (see `capsule_key_id`), so a host with
+/// several user secrets can select the matching one; `recover` fails with a dedicated error before
+/// the KDF is invoked if the identifiers don't match. Password capsules don't store an identifier.
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn key_id(sink: *mut sys::write_t, auth: *const sys::slice_t) -> *const c_char {
+	try_catch(|| {
+		let auth = auth.checked_slice().map_err(|_| Error::MissingAuth)?;
+		sink.checked_write(RawKey::key_id(auth)?)
+	})
+}
+
+
+/// Writes the 8 byte user secret identifier stored in a capsule to `sink`; nothing is written if
+/// the capsule does not contain an identifier (e.g. legacy, password or multi-recipient capsules)
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn capsule_key_id(sink: *mut sys::write_t, capsule: *const sys::slice_t)
+	-> *const c_char
+{
+	try_catch(|| match crypto::inspect(capsule.checked_slice()?)?.key_id {
+		Some(key_id) => sink.checked_write(key_id),
+		None => Ok(())
+	})
+}
+
+
+/// Checks that a capsule can be recovered with `auth` without writing the secret anywhere
+///
+/// This performs the same key derivation and decryption as `recover` but wipes the recovered secret
+/// immediately, so it can be used for health checks of stored capsules.
+///
+/// Returns `NULL` if the capsule can be recovered or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn verify(capsule: *const sys::slice_t, auth: *const sys::slice_t) -> *const c_char {
+	try_catch(|| {
+		let auth = auth.checked_slice().map_err(|_| Error::MissingAuth)?;
+		let capsule = capsule.checked_slice()?;
+		log::log(Level::Debug, format!("Verifying a {} byte capsule", capsule.len()));
+		rawkey().verify(capsule, auth)
+	})
+}
+
+
+/// Protects the data read from `source` using a stream config
+///
+/// Unlike `protect`, the data is pulled from `source` incrementally, so the host does not need to
+/// hold it in memory; each sealed chunk is written as separate segment.
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn protect_stream(sink: *mut sys::write_t, source: *mut sys::read_t,
+	config: *const sys::slice_t, auth: *const sys::slice_t) -> *const c_char
+{
+	try_catch(|| {
+		// Validate the passed config
+		let config = Config::from_name(config.checked_slice()?).ok_or(Error::InvalidConfig)?;
+		
+		// Protect the data
+		let auth = auth.checked_slice().map_err(|_| Error::MissingAuth)?;
+		log::log(Level::Debug, format!("Protecting a stream using {:?}", config));
+		let input = |buf: &mut[u8]| source.checked_read(buf);
+		rawkey().protect_stream(input, auth, config, |segment| sink.checked_write(segment))
+	})
+}
+
+
+/// Recovers the data from a stream capsule read from `source`
+///
+/// Unlike `recover`, the capsule is pulled from `source` incrementally; each authenticated chunk is
+/// written as separate segment. If an error is returned, all segments written so far must be
+/// discarded.
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn recover_stream(sink: *mut sys::write_t, source: *mut sys::read_t,
+	auth: *const sys::slice_t) -> *const c_char
+{
+	try_catch(|| {
+		let auth = auth.checked_slice().map_err(|_| Error::MissingAuth)?;
+		log::log(Level::Debug, "Recovering a stream capsule");
+		let input = |buf: &mut[u8]| source.checked_read(buf);
+		rawkey().recover_stream(input, auth, |chunk| sink.checked_write(chunk))
+	})
+}
+
+
+/// Parses the public metadata of a capsule without the user secret and writes each field as
+/// separate `key=value` segment
+///
+/// The fields are `version`, `config`, `context` (`true` or `false`), `argon2` (`m_cost,t_cost,
+/// p_cost`; password configs only), `threshold` and `recipients` (only if greater than `1`), `salt`
+/// (lowercase hex; single-recipient capsules only), `key_id` (lowercase hex; see `key_id`),
+/// `padded` (only if the secret is padded) and `secret_len` (including the padding).
+///
+/// Returns `NULL` on success or a pointer to a static error description (e.g. if the capsule is
+/// truncated)
+#[no_mangle]
+pub extern "C" fn inspect(sink: *mut sys::write_t, capsule: *const sys::slice_t) -> *const c_char {
+	try_catch(|| {
+		let info = crypto::inspect(capsule.checked_slice()?)?;
+		info.key_values().iter().try_for_each(|key_value| sink.checked_write(key_value))
+	})
+}
+
+
+/// Re-seals a capsule under a new user secret without exposing the secret to the host
+///
+/// `new_config` selects the config of the new capsule (e.g. to upgrade a legacy capsule); if it is
+/// `NULL`, the config of the capsule is kept. The intermediate secret is wiped after it is sealed.
+/// Capsules with multiple recipient or share slots are rejected since the other recipients would be
+/// dropped.
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn rewrap(sink: *mut sys::write_t, capsule: *const sys::slice_t,
+	old_auth: *const sys::slice_t, new_auth: *const sys::slice_t, new_config: *const sys::slice_t)
+	-> *const c_char
+{
+	try_catch(|| {
+		// Validate the passed config
+		let config = match new_config.is_null() {
+			true => None,
+			false => {
+				let name = new_config.checked_slice()?;
+				Some(Config::from_name(name).ok_or(Error::InvalidConfig)?)
+			}
+		};
+		
+		// Rewrap the capsule
+		let old_auth = old_auth.checked_slice().map_err(|_| Error::MissingAuth)?;
+		let new_auth = new_auth.checked_slice().map_err(|_| Error::MissingAuth)?;
+		let capsule = capsule.checked_slice()?;
+		log::log(Level::Debug, format!("Rewrapping a {} byte capsule", capsule.len()));
+		let rewrapped = rawkey().rewrap(capsule, old_auth, new_auth, config)?;
+		sink.checked_write(&rewrapped)
+	})
+}
+
+
+/// Generates a new high-entropy user secret with `bits` bits of entropy (`128` to `256`, a multiple
+/// of `8`) and writes it as grouped, checksummed Base58 string (e.g. `oGKqY-Yx8wR-...`) to `sink`
+///
+/// The string itself is the user secret; use `decode_user_secret` to detect typos before `recover`.
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn generate_user_secret(sink: *mut sys::write_t, bits: u32) -> *const c_char {
+	try_catch(|| sink.checked_write(&UserSecret::generate(bits)?))
+}
+
+
+/// Generates `bits / 8` random bytes (see `generate_user_secret`) and writes them to `sink`
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn generate_raw_user_secret(sink: *mut sys::write_t, bits: u32) -> *const c_char {
+	try_catch(|| sink.checked_write(&UserSecret::generate_raw(bits)?))
+}
+
+
+/// Validates the checksum and grouping of a user secret created by `generate_user_secret` and
+/// writes the decoded random bytes to `sink`
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn decode_user_secret(sink: *mut sys::write_t, encoded: *const sys::slice_t)
+	-> *const c_char
+{
+	try_catch(|| sink.checked_write(&UserSecret::decode(encoded.checked_slice()?)?))
+}
+
+
+/// Protects some data so that it can be recovered with any of the `auths_len` user secrets in the
+/// `auths` array (at most 16)
+///
+/// The data is sealed with a random data-encryption key which is sealed into a separate recipient
+/// slot for each user secret; `recover` tries all slots. Password configs are not supported.
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn protect_multi(sink: *mut sys::write_t, data: *const sys::slice_t,
+	config: *const sys::slice_t, auths: *const sys::slice_t, auths_len: usize) -> *const c_char
+{
+	try_catch(|| {
+		// Validate the passed config
+		let config = Config::from_name(config.checked_slice()?).ok_or(Error::InvalidConfig)?;
+		
+		// Protect the key
+		let auths = auths.checked_slices(auths_len).map_err(|_| Error::MissingAuth)?;
+		let data = data.checked_slice()?;
+		log::log(Level::Debug, format!(
+			"Protecting {} bytes for {} recipients using {:?}", data.len(), auths.len(), config
+		));
+		let protected = rawkey().protect_multi(data, &auths, config)?;
+		sink.checked_write(&protected)
+	})
+}
+
+
+/// Splits some data into shares so that it can be recovered with any `threshold` of the `auths_len`
+/// user secrets in the `auths` array (at most 16) using a threshold config
+///
+/// The data is sealed with a random data-encryption key which is split into Shamir shares over
+/// GF(256); each share is sealed into a separate slot with one of the user secrets.
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn protect_threshold(sink: *mut sys::write_t, data: *const sys::slice_t,
+	config: *const sys::slice_t, threshold: u8, auths: *const sys::slice_t, auths_len: usize)
+	-> *const c_char
+{
+	try_catch(|| {
+		// Validate the passed config
+		let config = Config::from_name(config.checked_slice()?).ok_or(Error::InvalidConfig)?;
+		
+		// Protect the key
+		let auths = auths.checked_slices(auths_len).map_err(|_| Error::MissingAuth)?;
+		let data = data.checked_slice()?;
+		log::log(Level::Debug, format!(
+			"Protecting {} bytes for {} of {} recipients using {:?}", data.len(), threshold,
+			auths.len(), config
+		));
+		let protected = rawkey().protect_threshold(data, threshold as usize, &auths, config)?;
+		sink.checked_write(&protected)
+	})
+}
+
+
+/// Recovers some data from a threshold capsule with the `auths_len` user secrets in the `auths`
+/// array (in any order)
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn recover_threshold(sink: *mut sys::write_t, data: *const sys::slice_t,
+	auths: *const sys::slice_t, auths_len: usize) -> *const c_char
+{
+	try_catch(|| {
+		// Recover the key
+		let auths = auths.checked_slices(auths_len).map_err(|_| Error::MissingAuth)?;
+		let data = data.checked_slice()?;
+		log::log(Level::Debug, format!("Recovering a {} byte threshold capsule", data.len()));
+		let recovered = rawkey().recover_threshold(data, &auths)?;
+		sink.checked_write(&recovered)
+	})
+}
+
+
+/// Adds a recipient slot for `new_auth` to a capsule using the user secret `auth` of an existing
+/// recipient; single-recipient capsules (except password capsules) are converted into
+/// multi-recipient capsules
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn add_recipient(sink: *mut sys::write_t, capsule: *const sys::slice_t,
+	auth: *const sys::slice_t, new_auth: *const sys::slice_t) -> *const c_char
+{
+	try_catch(|| {
+		let auth = auth.checked_slice().map_err(|_| Error::MissingAuth)?;
+		let new_auth = new_auth.checked_slice().map_err(|_| Error::MissingAuth)?;
+		let capsule = rawkey().add_recipient(capsule.checked_slice()?, auth, new_auth)?;
+		sink.checked_write(&capsule)
+	})
+}
+
+
+/// Removes the recipient slot at `index` (the slots are ordered by creation) from a multi-recipient
+/// capsule; `auth` must be the user secret of one of the remaining recipients
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn remove_recipient(sink: *mut sys::write_t, capsule: *const sys::slice_t,
+	auth: *const sys::slice_t, index: u64) -> *const c_char
+{
+	try_catch(|| {
+		let auth = auth.checked_slice().map_err(|_| Error::MissingAuth)?;
+		let index = usize::try_from(index).unwrap_or(usize::MAX);
+		let capsule = rawkey().remove_recipient(capsule.checked_slice()?, auth, index)?;
+		sink.checked_write(&capsule)
+	})
+}
+
+
+/// Maps an error pointer returned by any function of this library to its stable numeric error code
+///
+/// Returns `0` for `NULL` (i.e. success), the error code (see `Error`) for an error pointer returned
+/// by this library or `UINT32_MAX` for an unknown pointer
+#[no_mangle]
+pub extern "C" fn error_code(error: *const c_char) -> u32 {
+	match error.is_null() {
+		true => Error::CODE_OK,
+		false => Error::from_ptr(error).map(Error::code).unwrap_or(Error::CODE_UNKNOWN)
+	}
+}
+
+
+/// Writes the detailed description of the most recent failure on the calling thread (including
+/// the underlying error if any) to `sink`; nothing is written if no failure has occurred yet
+///
+/// Returns `NULL` on success or a pointer to a static error description
+#[no_mangle]
+pub extern "C" fn last_error_detail(sink: *mut sys::write_t) -> *const c_char {
+	let detail = ffi::last_error_detail();
+	try_catch(|| match detail {
+		Some(detail) => sink.checked_write(detail),
+		None => Ok(())
 	})
 }
 
@@ -0,0 +1,91 @@
+use crate::ffi::sys;
+use std::{
+	os::raw::{ c_char, c_void },
+	sync::{ Mutex, atomic::{ AtomicU8, Ordering::SeqCst } }
+};
+
+
+/// The global log level
+static LEVEL: AtomicU8 = AtomicU8::new(0);
+/// The host provided log sink (if any)
+static SINK: Mutex<Option<Sink>> = Mutex::new(None);
+
+
+/// A log level
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[repr(u8)]
+pub enum Level {
+	/// Failures
+	Error = 1,
+	/// Unexpected but recoverable conditions
+	Warn = 2,
+	/// High level information about the performed operations
+	Info = 3,
+	/// Detailed information about the performed operations
+	Debug = 4,
+	/// Very verbose diagnostics
+	Trace = 5
+}
+impl Level {
+	/// The level name
+	fn name(self) -> &'static str {
+		match self {
+			Self::Error => "error",
+			Self::Warn => "warn",
+			Self::Info => "info",
+			Self::Debug => "debug",
+			Self::Trace => "trace"
+		}
+	}
+}
+
+
+/// A copy of a host provided `write_t`
+#[derive(Copy, Clone)]
+struct Sink {
+	handle: *mut c_void,
+	write: unsafe extern "C" fn(handle: *mut c_void, data: *const sys::slice_t) -> *const c_char
+}
+// The host is responsible to provide a sink that can be used from any thread
+unsafe impl Send for Sink {}
+
+
+/// Sets the log level (`0` disables logging, `1` to `5` select the levels `error` to `trace`)
+pub fn set_level(level: u8) {
+	LEVEL.store(level, SeqCst)
+}
+
+/// Sets the log sink or restores logging to stderr if `sink` is `NULL`
+///
+/// Returns `false` if `sink` is not `NULL` but invalid
+pub fn set_sink(sink: *const sys::write_t) -> bool {
+	let sink = match unsafe{ sink.as_ref() } {
+		None => None,
+		Some(sys::write_t{ handle, write: Some(write) }) if !handle.is_null() =>
+			Some(Sink { handle: *handle, write: *write }),
+		Some(_) => return false
+	};
+	*SINK.lock().unwrap_or_else(|e| e.into_inner()) = sink;
+	true
+}
+
+/// Logs some text with `level`
+///
+/// The text is written as `"[level] text"` to the host provided log sink or to stderr
+pub fn log(level: Level, s: impl AsRef<str>) {
+	if level as u8 > LEVEL.load(SeqCst) {
+		return
+	}
+	
+	// Copy the sink to avoid holding the lock during the callback
+	let line = format!("[{}] {}", level.name(), s.as_ref());
+	let sink = *SINK.lock().unwrap_or_else(|e| e.into_inner());
+	match sink {
+		Some(sink) => {
+			// Errors are ignored because there is nowhere to report them to
+			let slice = sys::slice_t{ ptr: line.as_ptr(), len: line.len() };
+			let _ = unsafe{ (sink.write)(sink.handle, &slice) };
+		},
+		None => eprintln!("{}", line)
+	}
+}
\ No newline at end of file
@@ -0,0 +1,58 @@
+use crate::{ error::Error, ffi::ResultLogExt };
+
+
+/// The policy that a user secret must satisfy to create new capsules with a key config
+///
+/// Password configs stretch the user secret with Argon2id and are therefore not subject to the
+/// policy; existing capsules can always be recovered.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct AuthPolicy {
+	/// The minimum length of the user secret in bytes
+	pub min_len: usize,
+	/// Whether obviously low-entropy user secrets (i.e. short repeated patterns or short strings
+	/// that only consist of lowercase ASCII letters) are rejected
+	pub check_entropy: bool
+}
+impl AuthPolicy {
+	/// The default policy
+	pub const DEFAULT: Self = Self { min_len: 16, check_entropy: true };
+	/// A policy that accepts any user secret (only for legacy user secrets)
+	pub const DISABLED: Self = Self { min_len: 0, check_entropy: false };
+	
+	/// The longest pattern that is detected as repetition (e.g. `aaaa...` or `abcdabcd...`)
+	const MAX_PATTERN_LEN: usize = 4;
+	/// The minimum length of a lowercase-only user secret (`26^28` is roughly `2^128`)
+	const MIN_LOWERCASE_LEN: usize = 28;
+	
+	/// Checks if `auth` satisfies the policy
+	pub fn check(self, auth: &[u8]) -> Result<(), Error> {
+		/// Logs and records `reason` as cause
+		fn reject(reason: String) -> Result<(), Error> {
+			Err(reason).log_map_err(Error::WeakAuth)
+		}
+		
+		// Validate the length
+		if auth.len() < self.min_len {
+			reject(format!("The user secret is shorter than {} bytes", self.min_len))?
+		}
+		if !self.check_entropy {
+			return Ok(())
+		}
+		
+		// Detect repeated patterns and short lowercase strings
+		let is_repeated = (1 ..= Self::MAX_PATTERN_LEN)
+			.any(|len| auth.len() > len && auth[len..].iter().zip(auth).all(|(a, b)| a == b));
+		if is_repeated {
+			reject("The user secret is a repeated pattern".to_string())?
+		}
+		if auth.len() < Self::MIN_LOWERCASE_LEN && auth.iter().all(u8::is_ascii_lowercase) {
+			reject("The user secret is a short lowercase string".to_string())?
+		}
+		Ok(())
+	}
+}
+impl Default for AuthPolicy {
+	fn default() -> Self {
+		Self::DEFAULT
+	}
+}
\ No newline at end of file
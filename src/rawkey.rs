@@ -0,0 +1,194 @@
+use crate::{
+	capsule::CapsuleInfo, error::Error, policy::AuthPolicy, secret::Secret,
+	crypto::{ self, Argon2Params, Config, Padding }
+};
+
+
+/// A sealed capsule
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Capsule(Vec<u8>);
+impl Capsule {
+	/// The raw capsule bytes
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.0
+	}
+	/// Converts the capsule into its raw bytes
+	pub fn into_vec(self) -> Vec<u8> {
+		self.0
+	}
+	
+	/// Parses the public capsule metadata (which does not require the user secret)
+	pub fn info(&self) -> Result<CapsuleInfo, Error> {
+		crypto::inspect(&self.0)
+	}
+}
+impl From<Vec<u8>> for Capsule {
+	fn from(bytes: Vec<u8>) -> Self {
+		Self(bytes)
+	}
+}
+impl AsRef<[u8]> for Capsule {
+	fn as_ref(&self) -> &[u8] {
+		&self.0
+	}
+}
+
+
+/// The safe Rust API to protect and recover secrets
+///
+/// The C API is a thin wrapper around this type
+#[derive(Debug, Clone, Default)]
+pub struct RawKey {
+	/// The optional application specific context
+	context: Option<Vec<u8>>,
+	/// The Argon2id parameters for new password capsules
+	argon2: Argon2Params,
+	/// The policy for user secrets of new capsules
+	policy: AuthPolicy,
+	/// The length-hiding padding of new capsules
+	padding: Padding
+}
+impl RawKey {
+	/// Creates a new instance without an application specific context
+	pub fn new() -> Self {
+		Self::default()
+	}
+	/// Creates a new instance that binds all capsules to an application specific `context`
+	pub fn with_context(context: impl Into<Vec<u8>>) -> Self {
+		Self { context: Some(context.into()), ..Self::default() }
+	}
+	
+	/// Computes the non-secret identifier of the user secret `auth` which is stored in capsules that
+	/// are created with a key config (see `CapsuleInfo::key_id`)
+	pub fn key_id(auth: &[u8]) -> Result<[u8; 8], Error> {
+		crypto::key_id(auth)
+	}
+	
+	/// Sets the Argon2id parameters that are used to create new capsules with a password config
+	/// (the default is `Argon2Params::DEFAULT`)
+	pub fn set_argon2_params(&mut self, params: Argon2Params) {
+		self.argon2 = params
+	}
+	/// Sets the policy that the user secret must satisfy to create new capsules with a key config
+	/// (the default is `AuthPolicy::DEFAULT`; use `AuthPolicy::DISABLED` for legacy user secrets)
+	pub fn set_auth_policy(&mut self, policy: AuthPolicy) {
+		self.policy = policy
+	}
+	/// Sets the padding that is applied to the secret of new capsules to hide its length (the
+	/// default is `Padding::None`; stream configs cannot be padded and fail if a padding is set)
+	pub fn set_padding(&mut self, padding: Padding) {
+		self.padding = padding
+	}
+	
+	/// Protects `secret` with the user secret `auth` using `config`
+	pub fn protect(&self, secret: &[u8], auth: &[u8], config: Config) -> Result<Capsule, Error> {
+		if !config.is_password() {
+			self.policy.check(auth)?;
+		}
+		let context = self.context.as_deref();
+		crypto::protect(config, auth, context, self.argon2, self.padding, secret).map(Capsule)
+	}
+	/// Recovers the secret from `capsule` with the user secret `auth`
+	pub fn recover(&self, capsule: impl AsRef<[u8]>, auth: &[u8]) -> Result<Secret, Error> {
+		crypto::recover(auth, self.context.as_deref(), capsule.as_ref())
+	}
+	/// Checks that `capsule` can be recovered with the user secret `auth`; the recovered secret is
+	/// wiped and never returned to the caller
+	pub fn verify(&self, capsule: impl AsRef<[u8]>, auth: &[u8]) -> Result<(), Error> {
+		// Stream capsules are verified chunk by chunk instead of recovering them as a whole
+		let (capsule, context) = (capsule.as_ref(), self.context.as_deref());
+		match crypto::inspect(capsule).map(|info| info.config.is_stream()) {
+			Ok(true) => {
+				let input = crypto::read_slice(capsule);
+				crypto::recover_stream(auth, context, input, |_| Ok(()))
+			},
+			_ => crypto::recover(auth, context, capsule).map(|_| ())
+		}
+	}
+	/// Protects the data read from `input` with the user secret `auth` using the stream `config` and
+	/// passes the capsule to `output` segment by segment
+	pub fn protect_stream(&self, input: impl FnMut(&mut[u8]) -> Result<usize, Error>, auth: &[u8],
+		config: Config, output: impl FnMut(&[u8]) -> Result<(), Error>) -> Result<(), Error>
+	{
+		if self.padding != Padding::None {
+			Err(Error::InvalidPadding)?
+		}
+		self.policy.check(auth)?;
+		crypto::protect_stream(config, auth, self.context.as_deref(), input, output)
+	}
+	/// Recovers the data from the stream capsule read from `input` with the user secret `auth` and
+	/// passes it to `output` chunk by chunk; if an error is returned, all output must be discarded
+	pub fn recover_stream(&self, input: impl FnMut(&mut[u8]) -> Result<usize, Error>, auth: &[u8],
+		output: impl FnMut(&[u8]) -> Result<(), Error>) -> Result<(), Error>
+	{
+		crypto::recover_stream(auth, self.context.as_deref(), input, output)
+	}
+	/// Re-seals the secret in `capsule` under the new user secret `new_auth` using `config` (or the
+	/// capsule's config if `None`); the recovered secret is wiped and never returned to the caller
+	///
+	/// Capsules with multiple recipient or share slots are rejected with `Error::InvalidRecipients`
+	/// since the other recipients would be dropped; use `add_recipient` and `remove_recipient`
+	/// instead. Padded capsules keep their padded length, other capsules use the configured padding.
+	pub fn rewrap(&self, capsule: impl AsRef<[u8]>, old_auth: &[u8], new_auth: &[u8],
+		config: Option<Config>) -> Result<Capsule, Error>
+	{
+		let capsule = capsule.as_ref();
+		let info = crypto::inspect(capsule)?;
+		if info.recipients > 1 {
+			Err(Error::InvalidRecipients)?
+		}
+		let config = config.unwrap_or(info.config);
+		
+		// Keep the length hiding of padded capsules
+		let mut rawkey = self.clone();
+		if info.padded {
+			rawkey.padding = Padding::of(&info);
+		}
+		let secret = self.recover(capsule, old_auth)?;
+		rawkey.protect(&secret, new_auth, config)
+	}
+	
+	/// Protects `secret` so that it can be recovered with any of the (at most 16) user secrets
+	/// `auths` using the key `config` (password configs are not supported)
+	pub fn protect_multi(&self, secret: &[u8], auths: &[&[u8]], config: Config)
+		-> Result<Capsule, Error>
+	{
+		auths.iter().try_for_each(|auth| self.policy.check(auth))?;
+		let context = self.context.as_deref();
+		crypto::protect_multi(config, auths, context, self.padding, secret).map(Capsule)
+	}
+	/// Splits `secret` into shares so that it can be recovered with any `threshold` of the (at most
+	/// 16) user secrets `auths` using the threshold `config`
+	pub fn protect_threshold(&self, secret: &[u8], threshold: usize, auths: &[&[u8]], config: Config)
+		-> Result<Capsule, Error>
+	{
+		auths.iter().try_for_each(|auth| self.policy.check(auth))?;
+		let context = self.context.as_deref();
+		crypto::protect_threshold(config, threshold, auths, context, self.padding, secret)
+			.map(Capsule)
+	}
+	/// Recovers the secret from the threshold `capsule` with the user secrets `auths` (in any order)
+	pub fn recover_threshold(&self, capsule: impl AsRef<[u8]>, auths: &[&[u8]])
+		-> Result<Secret, Error>
+	{
+		crypto::recover_threshold(auths, self.context.as_deref(), capsule.as_ref())
+	}
+	/// Adds a recipient slot for `new_auth` to `capsule` using the existing recipient `auth`
+	///
+	/// Single-recipient capsules (except password capsules) are converted into multi-recipient
+	/// capsules. The slots are appended, so the slot index of a recipient is the index of its user
+	/// secret in the order of creation.
+	pub fn add_recipient(&self, capsule: impl AsRef<[u8]>, auth: &[u8], new_auth: &[u8])
+		-> Result<Capsule, Error>
+	{
+		self.policy.check(new_auth)?;
+		crypto::add_recipient(auth, new_auth, self.context.as_deref(), capsule.as_ref()).map(Capsule)
+	}
+	/// Removes the recipient slot at `index` from `capsule`; `auth` must be the user secret of one
+	/// of the remaining recipients
+	pub fn remove_recipient(&self, capsule: impl AsRef<[u8]>, auth: &[u8], index: usize)
+		-> Result<Capsule, Error>
+	{
+		crypto::remove_recipient(auth, self.context.as_deref(), capsule.as_ref(), index).map(Capsule)
+	}
+}
\ No newline at end of file
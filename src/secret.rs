@@ -0,0 +1,72 @@
+use std::{
+	ptr,
+	fmt::{ self, Debug, Formatter },
+	ops::{ Deref, DerefMut },
+	sync::atomic::{ self, Ordering::SeqCst }
+};
+
+
+/// Securely overwrites `buf` with zero bytes
+pub fn wipe(buf: &mut[u8]) {
+	buf.iter_mut().for_each(|b| unsafe{ ptr::write_volatile(b, 0) });
+	atomic::compiler_fence(SeqCst);
+}
+
+
+/// A fixed-size heap buffer for secret data that is wiped on drop
+///
+/// This ensures that key material is erased even if the `ma_proper` allocator is not used (e.g. for
+/// builds with `--no-default-features`)
+pub struct Secret(Vec<u8>);
+impl Secret {
+	/// Creates a new zeroed `len`-sized buffer
+	pub fn new(len: usize) -> Self {
+		Self(vec![0; len])
+	}
+	
+	/// Shortens the buffer to `len` bytes and wipes the removed bytes
+	pub fn truncate(&mut self, len: usize) {
+		if len < self.0.len() {
+			wipe(&mut self.0[len..]);
+			self.0.truncate(len);
+		}
+	}
+	/// Releases the underlying vector _without_ wiping it
+	///
+	/// This function must only be used if the buffer does not contain secret data anymore (e.g. if
+	/// it contains a sealed capsule)
+	pub fn into_vec(mut self) -> Vec<u8> {
+		std::mem::take(&mut self.0)
+	}
+}
+impl From<Vec<u8>> for Secret {
+	fn from(data: Vec<u8>) -> Self {
+		Self(data)
+	}
+}
+impl Deref for Secret {
+	type Target = [u8];
+	fn deref(&self) -> &[u8] {
+		&self.0
+	}
+}
+impl DerefMut for Secret {
+	fn deref_mut(&mut self) -> &mut [u8] {
+		&mut self.0
+	}
+}
+impl AsRef<[u8]> for Secret {
+	fn as_ref(&self) -> &[u8] {
+		&self.0
+	}
+}
+impl Debug for Secret {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		write!(f, "Secret(<{} bytes>)", self.0.len())
+	}
+}
+impl Drop for Secret {
+	fn drop(&mut self) {
+		wipe(&mut self.0)
+	}
+}
\ No newline at end of file
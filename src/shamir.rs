@@ -0,0 +1,98 @@
+use crate::{ crypto, error::Error, secret::Secret };
+
+
+/// Multiplies `a` and `b` in GF(256) with the reduction polynomial `x^8 + x^4 + x^3 + x + 1` in
+/// constant time
+fn mul(mut a: u8, mut b: u8) -> u8 {
+	let mut product = 0;
+	for _ in 0 .. 8 {
+		product ^= a & (b & 1).wrapping_neg();
+		a = (a << 1) ^ (0x1B & (a >> 7).wrapping_neg());
+		b >>= 1;
+	}
+	product
+}
+/// Computes the multiplicative inverse `a^254` of `a` in GF(256) (`0` is mapped to `0`)
+fn inv(a: u8) -> u8 {
+	let (mut result, mut square) = (1, a);
+	for bit in 0 .. 8 {
+		if 254 & (1 << bit) != 0 {
+			result = mul(result, square);
+		}
+		square = mul(square, square);
+	}
+	result
+}
+
+
+/// Splits `secret` into `count` shares so that any `threshold` shares can recover it (which
+/// requires `1 <= threshold <= count <= 255`)
+///
+/// ## Format
+/// Each share is `x[1] || y[secret.len()]` where `x` is the (non-zero) share index.
+pub fn split(secret: &[u8], threshold: usize, count: usize) -> Result<Vec<Secret>, Error> {
+	// Generate the random coefficients of a polynomial of degree `threshold - 1` per byte
+	let mut coefficients = Secret::new((threshold - 1) * secret.len());
+	crypto::random(&mut coefficients)?;
+	
+	// Evaluate the polynomials using Horner's method
+	let shares = (1 ..= count as u8).map(|x| {
+		let mut share = Secret::new(1 + secret.len());
+		share[0] = x;
+		for (pos, y) in share[1..].iter_mut().enumerate() {
+			let coefficients = coefficients.iter().skip(pos).step_by(secret.len());
+			let sum = coefficients.rev().fold(0, |sum, coefficient| mul(sum, x) ^ coefficient);
+			*y = mul(sum, x) ^ secret[pos];
+		}
+		share
+	});
+	Ok(shares.collect())
+}
+
+/// Recovers the secret from `shares` (which must have distinct indices and the same length) by
+/// interpolating the polynomials at `0`
+pub fn combine(shares: &[&[u8]]) -> Secret {
+	let len = shares.iter().map(|s| s.len().saturating_sub(1)).min().unwrap_or_default();
+	let mut secret = Secret::new(len);
+	for share in shares {
+		// Compute the Lagrange basis polynomial at `0`
+		let others = shares.iter().filter(|other| other[0] != share[0]);
+		let basis = others.fold(1, |basis, other| {
+			mul(basis, mul(other[0], inv(other[0] ^ share[0])))
+		});
+		
+		for (byte, y) in secret.iter_mut().zip(&share[1..]) {
+			*byte ^= mul(*y, basis);
+		}
+	}
+	secret
+}
+
+
+/// Tests the field arithmetic and that every `threshold`-subset of the shares recovers the secret
+#[test]
+fn test_shamir() {
+	// Test some known products and the inverses
+	assert_eq!(mul(0x53, 0xCA), 0x01);
+	assert_eq!(mul(0x57, 0x83), 0xC1);
+	assert_eq!(inv(0), 0);
+	for a in 1 ..= 255 {
+		assert_eq!(mul(a, inv(a)), 1);
+	}
+	
+	// Test all subsets of a 3-of-5 split
+	let secret = b"Testolope";
+	let shares = split(secret, 3, 5).unwrap();
+	for subset in 0u32 .. 1 << 5 {
+		let subset: Vec<&[u8]> = shares.iter().enumerate()
+			.filter(|(index, _)| subset & (1 << index) != 0)
+			.map(|(_, share)| &share[..]).collect();
+		match subset.len() {
+			3 ..= 5 => assert_eq!(&*combine(&subset), secret),
+			_ => assert_ne!(&*combine(&subset), secret)
+		}
+	}
+	
+	// A 1-of-n share contains the secret itself
+	assert_eq!(&split(secret, 1, 2).unwrap()[1][1..], secret);
+}
\ No newline at end of file
@@ -24,6 +24,24 @@ pub struct write_t {
 		) -> *const ::std::os::raw::c_char,
 	>,
 }
+#[doc = " A read callback"]
+#[repr(C)]
+#[derive(Debug)]
+pub struct read_t {
+	#[doc = " An opaque handle to the data source"]
+	pub handle: *mut ::std::os::raw::c_void,
+	#[doc = " Reads up to `buf_len` bytes from `handle` into `buf`, sets `read` to the amount of bytes read"]
+	#[doc = " (`0` at the end of the input) and returns `NULL` on success or a pointer to a static error"]
+	#[doc = " description"]
+	pub read: ::core::option::Option<
+		unsafe extern "C" fn(
+			handle: *mut ::std::os::raw::c_void,
+			buf: *mut u8,
+			buf_len: usize,
+			read: *mut usize,
+		) -> *const ::std::os::raw::c_char,
+	>,
+}
 #[doc = " Initializes the library with a specific API version and a logging level"]
 #[doc = ""]
 #[doc = " \\param api The required API version"]
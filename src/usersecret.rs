@@ -0,0 +1,128 @@
+use crate::{ crypto, error::Error, ffi::ResultLogExt, secret::Secret };
+use crypto_api_blake2::Blake2b;
+
+
+/// The Base58 alphabet (without the easily confused characters `0`, `O`, `I` and `l`)
+const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+/// The length of the checksum in bytes
+const CHECKSUM_LEN: usize = 4;
+/// The amount of characters per group
+const GROUP_LEN: usize = 5;
+
+
+/// Generates and encodes high-entropy user secrets
+///
+/// The encoded form is a human-transcribable Base58 string of `raw || checksum` in groups of five
+/// characters separated by `-` (like `oGKqY-Yx8wR-...`), where `checksum` are the first four bytes
+/// of `Blake2b(raw)`. The encoded string itself is used as user secret; decoding it detects typos
+/// before a capsule is opened.
+pub struct UserSecret;
+impl UserSecret {
+	/// The minimum strength in bits
+	pub const MIN_BITS: u32 = 128;
+	/// The maximum strength in bits (the encoded form of longer secrets exceeds the 64 byte key
+	/// limit of Blake2b-KDF)
+	pub const MAX_BITS: u32 = 256;
+	
+	/// Generates a new encoded user secret with `bits` bits of entropy
+	pub fn generate(bits: u32) -> Result<Secret, Error> {
+		Self::encode(&Self::generate_raw(bits)?)
+	}
+	/// Generates `bits / 8` random bytes
+	pub fn generate_raw(bits: u32) -> Result<Secret, Error> {
+		if bits & 7 != 0 {
+			Err(Error::InvalidSecretStrength)?
+		}
+		validate_len(bits as usize / 8)?;
+		
+		let mut raw = Secret::new(bits as usize / 8);
+		crypto::random(&mut raw)?;
+		Ok(raw)
+	}
+	
+	/// Encodes the `raw` user secret
+	pub fn encode(raw: &[u8]) -> Result<Secret, Error> {
+		validate_len(raw.len())?;
+		let mut data = Secret::new(raw.len() + CHECKSUM_LEN);
+		data[..raw.len()].copy_from_slice(raw);
+		data[raw.len()..].copy_from_slice(&checksum(raw)?);
+		
+		// Convert the data into little-endian Base58 digits
+		let mut digits = Secret::new(digits_len(data.len()));
+		for byte in data.iter() {
+			let mut carry = *byte as u32;
+			for digit in digits.iter_mut() {
+				carry += (*digit as u32) << 8;
+				*digit = (carry % 58) as u8;
+				carry /= 58;
+			}
+		}
+		
+		// Map the digits to the alphabet and group them
+		let mut encoded = Secret::new(digits.len() + (digits.len() - 1) / GROUP_LEN);
+		let chars = digits.iter().rev().map(|d| ALPHABET[*d as usize]);
+		for (pos, char) in chars.enumerate() {
+			if pos > 0 && pos % GROUP_LEN == 0 {
+				encoded[pos + pos / GROUP_LEN - 1] = b'-';
+			}
+			encoded[pos + pos / GROUP_LEN] = char;
+		}
+		Ok(encoded)
+	}
+	/// Decodes the `encoded` user secret and validates its checksum
+	pub fn decode(encoded: &[u8]) -> Result<Secret, Error> {
+		// Compute the data length from the amount of digits
+		let count = encoded.iter().filter(|c| **c != b'-').count();
+		let data_len = (Self::MIN_BITS as usize / 8 ..= Self::MAX_BITS as usize / 8)
+			.map(|len| len + CHECKSUM_LEN)
+			.find(|len| digits_len(*len) == count)
+			.ok_or(Error::InvalidUserSecret)?;
+		
+		// Convert the digits into big-endian bytes
+		let mut data = Secret::new(data_len);
+		for char in encoded.iter().filter(|c| **c != b'-') {
+			let digit = ALPHABET.iter().position(|a| a == char).ok_or(Error::InvalidUserSecret)?;
+			let mut carry = digit as u32;
+			for byte in data.iter_mut().rev() {
+				carry += *byte as u32 * 58;
+				*byte = carry as u8;
+				carry >>= 8;
+			}
+			if carry != 0 {
+				Err(Error::InvalidUserSecret)?
+			}
+		}
+		
+		// Validate the checksum and the grouping
+		let raw_len = data_len - CHECKSUM_LEN;
+		if data[raw_len..] != checksum(&data[..raw_len])? {
+			Err(Error::InvalidUserSecret)?
+		}
+		if *Self::encode(&data[..raw_len])? != *encoded {
+			Err(Error::InvalidUserSecret)?
+		}
+		
+		let mut raw = data;
+		raw.truncate(raw_len);
+		Ok(raw)
+	}
+}
+
+
+/// Validates the length of a raw user secret
+fn validate_len(len: usize) -> Result<(), Error> {
+	match (UserSecret::MIN_BITS as usize / 8 ..= UserSecret::MAX_BITS as usize / 8).contains(&len) {
+		true => Ok(()),
+		false => Err(Error::InvalidSecretStrength)
+	}
+}
+/// The amount of Base58 digits that are required to encode `len` bytes
+fn digits_len(len: usize) -> usize {
+	(len as f64 * 8.0 / 58f64.log2()).ceil() as usize
+}
+/// Computes the checksum of `raw`
+fn checksum(raw: &[u8]) -> Result<[u8; CHECKSUM_LEN], Error> {
+	let mut checksum = [0; CHECKSUM_LEN];
+	Blake2b::varlen_hash().varlen_hash(&mut checksum, raw).log_map_err(Error::Hash)?;
+	Ok(checksum)
+}
\ No newline at end of file
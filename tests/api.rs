@@ -0,0 +1,535 @@
+use kync_rawkey::{
+	Argon2Params, AuthPolicy, Capsule, Config, Error, Padding, RawKey, UserSecret, error_code, init
+};
+use std::{ ptr, io::Read };
+
+
+const USER_SECRET: &[u8] = b"oGKqY-Yx8wR-HFCMv-Y9Smh-N6oZb-p7ekX-tY3c5-ExCSY-vCG6c";
+
+
+/// Tests a `protect->recover` roundtrip for all configs
+#[test]
+fn test_roundtrip() {
+	let rawkey = RawKey::new();
+	for config in Config::ALL {
+		let capsule = rawkey.protect(b"Testolope", USER_SECRET, *config).unwrap();
+		let recovered = rawkey.recover(&capsule, USER_SECRET).unwrap();
+		assert_eq!(&*recovered, b"Testolope");
+	}
+}
+
+
+/// Tests that the context is respected
+#[test]
+fn test_context() {
+	let capsule = RawKey::with_context("db-master-key")
+		.protect(b"Testolope", USER_SECRET, Config::Blake2bXChachaPoly).unwrap();
+	
+	assert!(RawKey::with_context("backup-key").recover(&capsule, USER_SECRET).is_err());
+	assert!(RawKey::new().recover(&capsule, USER_SECRET).is_err());
+	
+	let recovered = RawKey::with_context("db-master-key").recover(&capsule, USER_SECRET).unwrap();
+	assert_eq!(&*recovered, b"Testolope");
+}
+
+
+/// Tests that an invalid user secret is rejected with a descriptive error
+#[test]
+fn test_invalid_auth() {
+	let capsule = RawKey::new().protect(b"Testolope", USER_SECRET, Config::Blake2bChachaPolyIetf)
+		.unwrap();
+	let capsule = Capsule::from(capsule.into_vec());
+	
+	let err = RawKey::new().recover(&capsule, b"Invalid").unwrap_err();
+	let description = "The user secret does not match the capsule's key identifier (wrong key)";
+	assert_eq!(err.to_string(), description);
+}
+
+/// Tests that the different failure modes can be distinguished
+#[test]
+fn test_error_kinds() {
+	let rawkey = RawKey::new();
+	let capsule = rawkey.protect(b"Testolope", USER_SECRET, Config::Blake2bXChachaPoly).unwrap();
+	let capsule = capsule.into_vec();
+	
+	// Invalid user secret
+	let err = rawkey.recover(&capsule, b"Invalid").unwrap_err();
+	assert_eq!(err, Error::WrongKey);
+	
+	// Damaged capsule
+	let mut damaged = capsule.clone();
+	*damaged.last_mut().unwrap() ^= 0x01;
+	let err = rawkey.recover(&damaged, USER_SECRET).unwrap_err();
+	assert_eq!(err, Error::Authentication);
+	
+	// Truncated capsule
+	let err = rawkey.recover(&capsule[..capsule.len() - 17], USER_SECRET).unwrap_err();
+	assert_eq!(err, Error::Truncated);
+	
+	// Unsupported version
+	let mut invalid = capsule.clone();
+	invalid[4] = 0xFF;
+	let err = rawkey.recover(&invalid, USER_SECRET).unwrap_err();
+	assert_eq!(err, Error::UnsupportedVersion);
+}
+
+
+/// Tests that the key commitment of a committing capsule is verified
+#[test]
+fn test_commitment() {
+	let rawkey = RawKey::new();
+	let capsule = rawkey.protect(b"Testolope", USER_SECRET, Config::Blake2bXChachaPolyCommitting)
+		.unwrap();
+	
+	// The commitment follows the header (including the key identifier), salt and nonce
+	for pos in 7 + 8 + 16 + 24 .. 7 + 8 + 16 + 24 + 32 {
+		let mut tampered = capsule.as_bytes().to_vec();
+		tampered[pos] ^= 0x01;
+		assert_eq!(rawkey.recover(&tampered, USER_SECRET).unwrap_err(), Error::Authentication);
+	}
+	assert_eq!(rawkey.recover(&capsule, b"Invalid").unwrap_err(), Error::WrongKey);
+}
+
+/// Tests that the key identifier is stored in key capsules and detects a wrong user secret early
+#[test]
+fn test_key_id() {
+	const OTHER: &[u8] = b"7dQxW-Jm2Lp-Vc9Rt-Hk4Ns-Bz6Gy";
+	let rawkey = RawKey::new();
+	let key_id = RawKey::key_id(USER_SECRET).unwrap();
+	
+	// Cross-checked with Python's `hashlib.blake2b(USER_SECRET, key=DOMAIN, digest_size=8)`
+	assert_eq!(&key_id, b"\xe6\x8a\x50\xa6\x64\x89\x90\xf7");
+	assert_ne!(key_id, RawKey::key_id(OTHER).unwrap());
+	
+	// The key identifier follows the fixed header fields
+	for config in Config::ALL {
+		let capsule = rawkey.protect(b"Testolope", USER_SECRET, *config).unwrap();
+		match config.is_password() || config.is_threshold() {
+			true => assert_eq!(capsule.info().unwrap().key_id, None),
+			false => {
+				assert_eq!(capsule.info().unwrap().key_id, Some(key_id));
+				assert_eq!(&capsule.as_bytes()[7..15], &key_id);
+				assert_eq!(rawkey.recover(&capsule, OTHER).unwrap_err(), Error::WrongKey);
+				
+				// The key identifier is bound to the capsule
+				let mut tampered = capsule.into_vec();
+				tampered[7] ^= 0x01;
+				assert_eq!(rawkey.recover(&tampered, USER_SECRET).unwrap_err(), Error::WrongKey);
+				tampered[6] ^= 0x04;
+				assert!(rawkey.recover(&tampered, USER_SECRET).is_err());
+			}
+		}
+	}
+	
+	// Multi-recipient capsules don't store a key identifier
+	let auths = [USER_SECRET, OTHER];
+	let capsule = rawkey.protect_multi(b"Testolope", &auths, Config::Blake2bXChachaPoly).unwrap();
+	assert_eq!(capsule.info().unwrap().key_id, None);
+}
+
+/// Tests the Argon2id parameter handling of password capsules
+#[test]
+fn test_argon2_params() {
+	let mut rawkey = RawKey::new();
+	
+	// Out-of-bounds parameters are rejected
+	let huge = Argon2Params { m_cost: Argon2Params::MAX.m_cost + 1, ..Argon2Params::MIN };
+	rawkey.set_argon2_params(huge);
+	let err = rawkey.protect(b"Testolope", b"passphrase", Config::Argon2idXChachaPoly).unwrap_err();
+	assert_eq!(err, Error::InvalidKdfParams);
+	
+	// The parameters are stored in the header
+	rawkey.set_argon2_params(Argon2Params::MIN);
+	let capsule = rawkey.protect(b"Testolope", b"passphrase", Config::Argon2idXChachaPoly).unwrap();
+	assert_eq!(capsule.info().unwrap().argon2, Some(Argon2Params::MIN));
+	assert_eq!(&*RawKey::new().recover(&capsule, b"passphrase").unwrap(), b"Testolope");
+	
+	// Non-password configs ignore the parameters
+	rawkey.set_argon2_params(huge);
+	let capsule = rawkey.protect(b"Testolope", USER_SECRET, Config::Blake2bXChachaPoly).unwrap();
+	assert_eq!(capsule.info().unwrap().argon2, None);
+}
+
+/// Tests that weak user secrets are rejected unless the policy is disabled
+#[test]
+fn test_auth_policy() {
+	let mut rawkey = RawKey::new();
+	let weak: &[&[u8]] =
+		&[b"x", b"Kq7-bT", &[0; 32], b"abcabcabcabcabcabcabc", b"correcthorsebattery"];
+	for weak in weak {
+		let err = rawkey.protect(b"Testolope", weak, Config::Blake2bXChachaPoly).unwrap_err();
+		assert_eq!(err, Error::WeakAuth);
+	}
+	
+	// Long lowercase strings and password configs are accepted
+	let lowercase = b"correcthorsebatterystaplecorrect";
+	assert!(rawkey.protect(b"Testolope", lowercase, Config::Blake2bXChachaPoly).is_ok());
+	rawkey.set_argon2_params(Argon2Params::MIN);
+	assert!(rawkey.protect(b"Testolope", b"x", Config::Argon2idXChachaPoly).is_ok());
+	
+	// Disable or tighten the policy
+	rawkey.set_auth_policy(AuthPolicy::DISABLED);
+	let capsule = rawkey.protect(b"Testolope", &[0; 32], Config::Blake2bXChachaPoly).unwrap();
+	assert_eq!(&*RawKey::new().recover(&capsule, &[0; 32]).unwrap(), b"Testolope");
+	
+	rawkey.set_auth_policy(AuthPolicy { min_len: 64, ..AuthPolicy::DEFAULT });
+	let err = rawkey.protect(b"Testolope", USER_SECRET, Config::Blake2bXChachaPoly).unwrap_err();
+	assert_eq!(err, Error::WeakAuth);
+}
+
+/// Tests that padded capsules hide the secret length and recover the unpadded secret
+#[test]
+fn test_padding() {
+	const CONFIGS: &[Config] = &[Config::Blake2bXChachaPoly, Config::Blake2bChachaPolyIetfShamir];
+	const BREAK_GLASS: &[u8] = b"7dQxW-Jm2Lp-Vc9Rt-Hk4Ns-Bz6Gy";
+	let mut rawkey = RawKey::new();
+	for config in CONFIGS {
+		// Pad to the next power of two or bucket
+		let paddings = [(Padding::PowerOfTwo, 16, 64), (Padding::Bucket(48), 48, 48)];
+		for (padding, short_len, long_len) in paddings {
+			rawkey.set_padding(padding);
+			for (secret, padded_len) in [(&b"Testolope"[..], short_len), (&[7; 32][..], long_len)] {
+				let capsule = rawkey.protect(secret, USER_SECRET, *config).unwrap();
+				let info = capsule.info().unwrap();
+				assert_eq!((info.padded, info.secret_len), (true, padded_len));
+				assert_eq!(&*rawkey.recover(&capsule, USER_SECRET).unwrap(), secret);
+			}
+		}
+		
+		// An empty or too large bucket is rejected
+		for bucket in [0, Padding::MAX_BUCKET_LEN + 1, usize::MAX] {
+			rawkey.set_padding(Padding::Bucket(bucket));
+			let err = rawkey.protect(b"Testolope", USER_SECRET, *config).unwrap_err();
+			assert_eq!(err, Error::InvalidPadding);
+		}
+		rawkey.set_padding(Padding::None);
+	}
+	
+	// Rewrapping keeps the padded length even if no padding is configured
+	rawkey.set_padding(Padding::Bucket(64));
+	let capsule = rawkey.protect(b"Testolope", USER_SECRET, Config::Blake2bXChachaPoly).unwrap();
+	let rewrapped = RawKey::new().rewrap(&capsule, USER_SECRET, BREAK_GLASS, None).unwrap();
+	let info = rewrapped.info().unwrap();
+	assert_eq!((info.padded, info.secret_len), (true, 64));
+	assert_eq!(&*rawkey.recover(&rewrapped, BREAK_GLASS).unwrap(), b"Testolope");
+	
+	// Adding a recipient keeps the padded length and stream capsules cannot be padded
+	rawkey.set_padding(Padding::PowerOfTwo);
+	let capsule = rawkey.protect(b"Testolope", USER_SECRET, Config::Blake2bXChachaPoly).unwrap();
+	let capsule = rawkey.add_recipient(&capsule, USER_SECRET, BREAK_GLASS).unwrap();
+	let info = capsule.info().unwrap();
+	assert_eq!((info.padded, info.secret_len, info.recipients), (true, 16, 2));
+	assert_eq!(&*rawkey.recover(&capsule, BREAK_GLASS).unwrap(), b"Testolope");
+	
+	let config = Config::Blake2bXChachaPolyStream;
+	let err = rawkey.protect(b"Testolope", USER_SECRET, config).unwrap_err();
+	assert_eq!(err, Error::InvalidPadding);
+	let mut input: &[u8] = b"Testolope";
+	let input = |buf: &mut[u8]| Ok(input.read(buf).unwrap());
+	let err = rawkey.protect_stream(input, USER_SECRET, config, |_| Ok(())).unwrap_err();
+	assert_eq!(err, Error::InvalidPadding);
+}
+
+/// Tests rewrapping a capsule under a new user secret and config
+#[test]
+fn test_rewrap() {
+	const NEW_USER_SECRET: &[u8] = b"7dQxW-Jm2Lp-Vc9Rt-Hk4Ns-Bz6Gy";
+	let rawkey = RawKey::new();
+	let capsule = rawkey.protect(b"Testolope", USER_SECRET, Config::Blake2bChachaPolyIetf).unwrap();
+	
+	// Keep the config
+	let rewrapped = rawkey.rewrap(&capsule, USER_SECRET, NEW_USER_SECRET, None).unwrap();
+	assert_eq!(rewrapped.info().unwrap().config, Config::Blake2bChachaPolyIetf);
+	assert_eq!(rawkey.recover(&rewrapped, USER_SECRET).unwrap_err(), Error::WrongKey);
+	assert_eq!(&*rawkey.recover(&rewrapped, NEW_USER_SECRET).unwrap(), b"Testolope");
+	
+	// Upgrade the config
+	let upgraded = rawkey.rewrap(&rewrapped, NEW_USER_SECRET, USER_SECRET,
+		Some(Config::Blake2bAes256GcmSiv)).unwrap();
+	assert_eq!(upgraded.info().unwrap().config, Config::Blake2bAes256GcmSiv);
+	assert_eq!(&*rawkey.recover(&upgraded, USER_SECRET).unwrap(), b"Testolope");
+	
+	// Use an invalid old or a weak new user secret
+	let err = rawkey.rewrap(&capsule, b"Invalid", NEW_USER_SECRET, None).unwrap_err();
+	assert_eq!(err, Error::WrongKey);
+	let err = rawkey.rewrap(&capsule, USER_SECRET, b"weak", None).unwrap_err();
+	assert_eq!(err, Error::WeakAuth);
+}
+
+/// Tests multi-recipient capsules and adding/removing recipient slots
+#[test]
+fn test_multi_recipient() {
+	const BREAK_GLASS: &[u8] = b"7dQxW-Jm2Lp-Vc9Rt-Hk4Ns-Bz6Gy";
+	const THIRD: &[u8] = b"Ue4cH-9sPqK-Tz2Xb-Rm7Wd-Lf3Jn";
+	let rawkey = RawKey::with_context("db-master-key");
+	for config in &[Config::Blake2bXChachaPoly, Config::Blake2bXChachaPolyCommitting] {
+		let capsule = rawkey.protect_multi(b"Testolope", &[USER_SECRET, BREAK_GLASS], *config)
+			.unwrap();
+		assert_eq!(capsule.info().unwrap().recipients, 2);
+		assert_eq!(capsule.info().unwrap().secret_len, 9);
+		for auth in &[USER_SECRET, BREAK_GLASS] {
+			assert_eq!(&*rawkey.recover(&capsule, auth).unwrap(), b"Testolope");
+		}
+		let err = rawkey.recover(&capsule, THIRD).unwrap_err();
+		assert_eq!(err, Error::ContextAuthentication);
+		
+		// Add a slot
+		let capsule = rawkey.add_recipient(&capsule, BREAK_GLASS, THIRD).unwrap();
+		assert_eq!(capsule.info().unwrap().recipients, 3);
+		assert_eq!(&*rawkey.recover(&capsule, THIRD).unwrap(), b"Testolope");
+		
+		// Remove the first slot
+		let err = rawkey.remove_recipient(&capsule, USER_SECRET, 0).unwrap_err();
+		assert_eq!(err, Error::ContextAuthentication);
+		let capsule = rawkey.remove_recipient(&capsule, THIRD, 0).unwrap();
+		assert_eq!(capsule.info().unwrap().recipients, 2);
+		assert!(rawkey.recover(&capsule, USER_SECRET).is_err());
+		assert_eq!(&*rawkey.recover(&capsule, BREAK_GLASS).unwrap(), b"Testolope");
+		assert_eq!(&*rawkey.recover(&capsule, THIRD).unwrap(), b"Testolope");
+		
+		// Use an invalid index or remove the last slot
+		let err = rawkey.remove_recipient(&capsule, THIRD, 2).unwrap_err();
+		assert_eq!(err, Error::InvalidRecipients);
+		let capsule = rawkey.remove_recipient(&capsule, THIRD, 0).unwrap();
+		let err = rawkey.remove_recipient(&capsule, THIRD, 0).unwrap_err();
+		assert_eq!(err, Error::InvalidRecipients);
+	}
+	
+	// Convert a single-recipient capsule
+	let capsule = rawkey.protect(b"Testolope", USER_SECRET, Config::Blake2bAes256GcmSiv).unwrap();
+	let err = rawkey.remove_recipient(&capsule, USER_SECRET, 0).unwrap_err();
+	assert_eq!(err, Error::InvalidRecipients);
+	let capsule = rawkey.add_recipient(&capsule, USER_SECRET, BREAK_GLASS).unwrap();
+	assert_eq!(capsule.info().unwrap().config, Config::Blake2bAes256GcmSiv);
+	assert_eq!(capsule.info().unwrap().recipients, 2);
+	assert_eq!(&*rawkey.recover(&capsule, BREAK_GLASS).unwrap(), b"Testolope");
+	
+	// Convert a capsule without context while a context is set
+	let unbound = RawKey::new();
+	let single = unbound.protect(b"Testolope", USER_SECRET, Config::Blake2bXChachaPoly).unwrap();
+	let converted = rawkey.add_recipient(&single, USER_SECRET, BREAK_GLASS).unwrap();
+	assert!(!converted.info().unwrap().context);
+	assert_eq!(&*unbound.recover(&converted, BREAK_GLASS).unwrap(), b"Testolope");
+	
+	// Rewrapping would drop the other recipients
+	let err = rawkey.rewrap(&capsule, BREAK_GLASS, THIRD, None).unwrap_err();
+	assert_eq!(err, Error::InvalidRecipients);
+	
+	// Use no or too many recipients
+	let err = rawkey.protect_multi(b"Testolope", &[], Config::Blake2bXChachaPoly).unwrap_err();
+	assert_eq!(err, Error::InvalidRecipients);
+	let auths = vec![USER_SECRET; 17];
+	let err = rawkey.protect_multi(b"Testolope", &auths, Config::Blake2bXChachaPoly).unwrap_err();
+	assert_eq!(err, Error::InvalidRecipients);
+	
+	// Password configs are rejected since each slot would run Argon2id
+	let auths = [USER_SECRET, BREAK_GLASS];
+	let err = rawkey.protect_multi(b"Testolope", &auths, Config::Argon2idXChachaPoly).unwrap_err();
+	assert_eq!(err, Error::InvalidConfig);
+	let mut crafted = capsule.into_vec();
+	crafted[5] = Config::Argon2idXChachaPoly.id();
+	assert_eq!(rawkey.recover(&crafted, USER_SECRET).unwrap_err(), Error::UnsupportedFlags);
+}
+
+/// A predefined `2`-of-`3` threshold capsule for the user secrets `USER_SECRET`, `BREAK_GLASS` and
+/// `THIRD` of `test_threshold` (secret: `Testolope`)
+const THRESHOLD_CAPSULE: &[u8] = b"\x52\x61\x77\x4b\x01\x08\x00\x02\x03\xfb\xe9\x1c\xd7\x61\x22\x79\x38\xe4\x30\xef\x9f\x43\x44\xb0\x53\xa5\x36\xc1\x6a\xbc\xb9\x86\xae\x48\xb7\x6f\x2a\xd3\xb8\xfd\xd1\x50\x24\x8c\xd9\x24\x4b\x1d\x18\xc6\xc0\x5b\x63\x3e\x21\x03\x34\x1f\xd3\xe5\x3f\xd3\xc8\x64\x54\x99\xc4\x4c\x1b\x6b\xa9\x8c\xd6\x6c\xd2\x14\xe1\x44\x90\x9e\xe4\x32\xba\x46\xa9\x23\x2d\xb0\x10\x3d\xbd\x7f\x3f\x86\xb0\xae\xcf\x29\x8a\x23\x98\xa2\x39\x82\x7d\x85\x72\x2c\x7c\x7e\x9b\xa0\x9d\xb1\x1a\x57\x16\x2c\xde\xfc\xfa\xbf\xe1\xa3\xb3\xda\x30\x9d\xa1\xff\xba\x0d\x5e\x86\x08\x06\xc5\xfc\xd1\xc9\x3b\x9f\x25\x3d\xb8\x85\x69\xa7\x91\x3e\xf2\x43\x39\x7b\xed\x8b\xd9\xe8\x29\x56\x8f\x43\x9a\x9a\x0c\x68\xb4\x41\xc4\x9d\x3d\x70\x2d\x70\x17\x0a\x1f\x67\x83\xad\x10\x27\x30\xa8\x33\x7c\x25\x91\x46\x25\xff\x72\xdd\x48\x32\x40\x95\xe1\x23\xf1\xdc\xf7\x6c\xbe\x65\xa3\xef\xdd\x32\x6e\x22\x99\xb9\x99\x2b\xa0\xa9\x18\x6a\x86\xda\x27\x48\x61\x0e\x5d\x90\xc7\xbe\xcb\xcd\x18\x08\xf8\xd3\x46\x40\x4c\xb1\x5b\x10\x37\x2f\xaa\xf1\x4a\x7a\xb1\xd5\x51\xf7\x91\xd6\x3f\x33\xa2\xa6\x59\x00\xa7\x5f\xf8\x2f\x3b\x46\x1d\xec\x9d\xb0\xce\xc8\x40\xc7\x1d\x32\xbf\x2c";
+
+/// Tests threshold capsules
+#[test]
+fn test_threshold() {
+	const BREAK_GLASS: &[u8] = b"7dQxW-Jm2Lp-Vc9Rt-Hk4Ns-Bz6Gy";
+	const THIRD: &[u8] = b"Ue4cH-9sPqK-Tz2Xb-Rm7Wd-Lf3Jn";
+	const CONFIG: Config = Config::Blake2bChachaPolyIetfShamir;
+	let rawkey = RawKey::new();
+	
+	// Recover the predefined capsule with any two user secrets
+	for auths in &[[USER_SECRET, BREAK_GLASS], [THIRD, USER_SECRET], [BREAK_GLASS, THIRD]] {
+		let recovered = rawkey.recover_threshold(THRESHOLD_CAPSULE, auths).unwrap();
+		assert_eq!(&*recovered, b"Testolope");
+	}
+	let err = rawkey.recover_threshold(THRESHOLD_CAPSULE, &[THIRD, b"Invalid"]).unwrap_err();
+	assert_eq!(err, Error::ThresholdNotMet);
+	assert_eq!(rawkey.recover(THRESHOLD_CAPSULE, THIRD).unwrap_err(), Error::ThresholdNotMet);
+	
+	// Create a new capsule
+	let auths = [USER_SECRET, BREAK_GLASS, THIRD];
+	let capsule = rawkey.protect_threshold(b"Testolope", 3, &auths, CONFIG).unwrap();
+	let info = capsule.info().unwrap();
+	assert_eq!((info.config, info.threshold, info.recipients), (CONFIG, 3, 3));
+	let recovered = rawkey.recover_threshold(&capsule, &[THIRD, BREAK_GLASS, USER_SECRET]).unwrap();
+	assert_eq!(&*recovered, b"Testolope");
+	let err = rawkey.recover_threshold(&capsule, &[USER_SECRET, THIRD]).unwrap_err();
+	assert_eq!(err, Error::ThresholdNotMet);
+	
+	// A single user secret creates a `1`-of-`1` capsule
+	let capsule = rawkey.protect(b"Testolope", USER_SECRET, CONFIG).unwrap();
+	assert_eq!(&*rawkey.recover(&capsule, USER_SECRET).unwrap(), b"Testolope");
+	assert_eq!(rawkey.recover(&capsule, THIRD).unwrap_err(), Error::Authentication);
+	
+	// Use an invalid threshold or config
+	for threshold in &[0, 4] {
+		let err = rawkey.protect_threshold(b"Testolope", *threshold, &auths, CONFIG).unwrap_err();
+		assert_eq!(err, Error::InvalidRecipients);
+	}
+	let duplicates = [USER_SECRET, USER_SECRET, THIRD];
+	let err = rawkey.protect_threshold(b"Testolope", 2, &duplicates, CONFIG).unwrap_err();
+	assert_eq!(err, Error::InvalidRecipients);
+	let err = rawkey.protect_threshold(b"Testolope", 2, &auths, Config::Blake2bXChachaPoly)
+		.unwrap_err();
+	assert_eq!(err, Error::InvalidConfig);
+	let err = rawkey.protect_multi(b"Testolope", &auths, CONFIG).unwrap_err();
+	assert_eq!(err, Error::InvalidConfig);
+}
+
+/// Tests chunked stream capsules and that truncations are detected
+#[test]
+fn test_stream() {
+	const CHUNK_LEN: usize = 64 * 1024;
+	const CONFIG: Config = Config::Blake2bXChachaPolyStream;
+	let rawkey = RawKey::with_context("backup-key");
+	
+	// Protects `data` and returns the segments
+	let protect = |data: &[u8]| {
+		let (mut input, mut segments) = (data, Vec::new());
+		rawkey.protect_stream(|buf| Ok(input.read(buf).unwrap()), USER_SECRET, CONFIG, |segment| {
+			segments.push(segment.to_vec());
+			Ok(())
+		}).map(|_| segments)
+	};
+	// Recovers the capsule and returns the chunks
+	let recover = |capsule: &[u8]| {
+		let (mut input, mut chunks) = (capsule, Vec::new());
+		rawkey.recover_stream(|buf| Ok(input.read(buf).unwrap()), USER_SECRET, |chunk| {
+			chunks.push(chunk.to_vec());
+			Ok(())
+		}).map(|_| chunks)
+	};
+	
+	for len in &[0, 1, CHUNK_LEN, 2 * CHUNK_LEN + 7] {
+		// Seal the data and recover it chunk by chunk and as a whole
+		let data: Vec<u8> = (0 .. *len).map(|i| i as u8).collect();
+		let segments = protect(&data).unwrap();
+		let chunk_count = (len.saturating_sub(1) / CHUNK_LEN) + 1;
+		assert_eq!(segments.len(), 1 + chunk_count);
+		
+		let capsule = segments.concat();
+		let chunks = recover(&capsule).unwrap();
+		assert_eq!(chunks.len(), chunk_count);
+		assert_eq!(chunks.concat(), data);
+		assert_eq!(&*rawkey.recover(&capsule, USER_SECRET).unwrap(), data.as_slice());
+		assert_eq!(Capsule::from(capsule).info().unwrap().secret_len, data.len());
+		
+		// Drop the final chunk
+		if chunk_count > 1 {
+			let truncated = segments[.. segments.len() - 1].concat();
+			assert_eq!(recover(&truncated).unwrap_err(), Error::StreamTruncated);
+		}
+		
+		// Append a chunk, swap the chunks or damage the final chunk
+		let extended = [segments.concat(), segments[1].clone()].concat();
+		assert_eq!(recover(&extended).unwrap_err(), Error::ContextAuthentication);
+		if chunk_count > 1 {
+			let mut swapped = segments.clone();
+			swapped.swap(1, 2);
+			assert_eq!(recover(&swapped.concat()).unwrap_err(), Error::ContextAuthentication);
+		}
+		let mut damaged = segments.concat();
+		*damaged.last_mut().unwrap() ^= 0x01;
+		assert_eq!(recover(&damaged).unwrap_err(), Error::ContextAuthentication);
+		assert_eq!(recover(&segments[0]).unwrap_err(), Error::Truncated);
+	}
+	
+	// Use a non-stream config or capsule
+	let mut input: &[u8] = b"Testolope";
+	let err = rawkey.protect_stream(|buf| Ok(input.read(buf).unwrap()), USER_SECRET,
+		Config::Blake2bXChachaPoly, |_| Ok(())).unwrap_err();
+	assert_eq!(err, Error::InvalidConfig);
+	let capsule = rawkey.protect(b"Testolope", USER_SECRET, Config::Blake2bXChachaPoly).unwrap();
+	assert_eq!(recover(capsule.as_bytes()).unwrap_err(), Error::InvalidConfig);
+	assert!(rawkey.protect_multi(b"Testolope", &[USER_SECRET], CONFIG).is_err());
+}
+
+/// Tests that capsules can be verified without recovering them
+#[test]
+fn test_verify() {
+	let rawkey = RawKey::with_context("db-master-key");
+	for config in Config::ALL {
+		let capsule = rawkey.protect(b"Testolope", USER_SECRET, *config).unwrap();
+		assert_eq!(rawkey.verify(&capsule, USER_SECRET), Ok(()));
+		let err = match capsule.info().unwrap().key_id {
+			Some(_) => Error::WrongKey,
+			None => Error::ContextAuthentication
+		};
+		assert_eq!(rawkey.verify(&capsule, b"Invalid"), Err(err));
+		assert_eq!(RawKey::new().verify(&capsule, USER_SECRET), Err(Error::MissingContext));
+		
+		let mut damaged = capsule.into_vec();
+		*damaged.last_mut().unwrap() ^= 0x01;
+		assert_eq!(rawkey.verify(&damaged, USER_SECRET), Err(Error::ContextAuthentication));
+		assert_eq!(rawkey.verify(&damaged[..10], USER_SECRET), Err(Error::Truncated));
+	}
+}
+
+/// Tests the user secret encoding and that typos are detected
+#[test]
+fn test_user_secret() {
+	const ENCODED: &[u8] = b"1116q-JFWMM-HFy3x-DdLmv-Ueyc2-S6FrW-RhJP5-1HsvD-Ydz9d-D5Mzz";
+	let raw: Vec<u8> = (0 .. 32).collect();
+	assert_eq!(&*UserSecret::encode(&raw).unwrap(), ENCODED);
+	assert_eq!(&*UserSecret::decode(ENCODED).unwrap(), raw.as_slice());
+	
+	// Substitute each character, swap adjacent characters and remove a character
+	for pos in 0 .. ENCODED.len() {
+		for char in b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz-0Ol".iter() {
+			let mut typo = ENCODED.to_vec();
+			typo[pos] = *char;
+			if typo != ENCODED {
+				assert_eq!(UserSecret::decode(&typo).unwrap_err(), Error::InvalidUserSecret);
+			}
+		}
+		if pos + 1 < ENCODED.len() && ENCODED[pos] != ENCODED[pos + 1] {
+			let mut typo = ENCODED.to_vec();
+			typo.swap(pos, pos + 1);
+			assert_eq!(UserSecret::decode(&typo).unwrap_err(), Error::InvalidUserSecret);
+		}
+		let mut typo = ENCODED.to_vec();
+		typo.remove(pos);
+		assert_eq!(UserSecret::decode(&typo).unwrap_err(), Error::InvalidUserSecret);
+	}
+	
+	// Generate user secrets and use them
+	for bits in &[128, 192, 256] {
+		let encoded = UserSecret::generate(*bits).unwrap();
+		assert_eq!(UserSecret::decode(&encoded).unwrap().len(), *bits as usize / 8);
+		for config in Config::ALL {
+			assert!(RawKey::new().protect(b"Testolope", &encoded, *config).is_ok());
+		}
+	}
+	for bits in &[0, 64, 129, 264] {
+		assert_eq!(UserSecret::generate(*bits).unwrap_err(), Error::InvalidSecretStrength);
+		assert_eq!(UserSecret::generate_raw(*bits).unwrap_err(), Error::InvalidSecretStrength);
+	}
+}
+
+/// Tests the stable error codes and their C API mapping
+#[test]
+fn test_error_codes() {
+	// Validate some stable codes
+	assert_eq!(Error::NullPointer.code(), 1);
+	assert_eq!(Error::SinkWrite.code(), 2);
+	assert_eq!(Error::Random.code(), 6);
+	assert_eq!(Error::Truncated.code(), 10);
+	assert_eq!(Error::Authentication.code(), 14);
+	
+	// Ensure that all codes are unique and that all pointers map back to their code
+	for (index, error) in Error::ALL.iter().enumerate() {
+		assert_eq!(error.code(), index as u32 + 1);
+		assert_eq!(error_code((*error).into()), error.code());
+	}
+	
+	// Test the special pointers
+	assert_eq!(error_code(ptr::null()), 0);
+	assert_eq!(error_code(b"Some foreign error\0".as_ptr().cast()), u32::MAX);
+	assert_eq!(error_code(init(0xFFFF, 0)), Error::UnsupportedApi.code());
+}
\ No newline at end of file
@@ -0,0 +1,175 @@
+use kync_rawkey::UserSecret;
+use std::{
+	env, fs,
+	path::PathBuf,
+	process::{ Command, Output },
+	sync::atomic::{ AtomicUsize, Ordering::SeqCst }
+};
+
+
+/// A temporary directory that is deleted on drop
+struct TempDir(PathBuf);
+impl TempDir {
+	/// Creates a new unique temporary directory
+	pub fn new() -> Self {
+		static COUNTER: AtomicUsize = AtomicUsize::new(0);
+		let name = format!("kync_rawkey-cli-{}-{}", std::process::id(), COUNTER.fetch_add(1, SeqCst));
+		let path = env::temp_dir().join(name);
+		fs::create_dir_all(&path).unwrap();
+		Self(path)
+	}
+	/// Creates a path to `name` within the directory
+	pub fn path(&self, name: &str) -> String {
+		self.0.join(name).to_str().unwrap().to_string()
+	}
+}
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.0);
+	}
+}
+
+
+/// Runs the `rawkey` binary with `args`
+fn rawkey(args: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_rawkey")).args(args).output().unwrap()
+}
+
+
+/// Tests a `keygen->seal->inspect->open` roundtrip using files
+#[test]
+fn test_roundtrip() {
+	let dir = TempDir::new();
+	let (user_secret, plain, capsule, recovered) =
+		(dir.path("user_secret"), dir.path("plain"), dir.path("capsule"), dir.path("recovered"));
+	fs::write(&plain, b"Testolope").unwrap();
+	
+	// Generate a user secret
+	assert!(rawkey(&["keygen", "--out", &user_secret]).status.success());
+	assert_eq!(fs::read(&user_secret).unwrap().len(), 32);
+	
+	// Seal the secret
+	let output = rawkey(&[
+		"seal", "--config", "Blake2b-ChaChaPolyIETF", "--in", &plain, "--out", &capsule,
+		"--secret-file", &user_secret
+	]);
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	
+	// Inspect the capsule
+	let output = rawkey(&["inspect", "--in", &capsule]);
+	assert!(output.status.success());
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	let lines: Vec<&str> = stdout.lines().collect();
+	assert_eq!(lines.len(), 6);
+	assert_eq!(lines[..3], ["version=1", "config=Blake2b-ChaChaPolyIETF", "context=false"]);
+	assert!(lines[3].starts_with("salt=") && lines[3].len() == 5 + 32);
+	assert!(lines[4].starts_with("key_id=") && lines[4].len() == 7 + 16);
+	assert_eq!(lines[5], "secret_len=9");
+	
+	// Open the capsule
+	let output =
+		rawkey(&["open", "--in", &capsule, "--out", &recovered, "--secret-file", &user_secret]);
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(fs::read(&recovered).unwrap(), b"Testolope");
+}
+
+
+/// Tests generating a checksummed text user secret
+#[test]
+fn test_keygen_text() {
+	let dir = TempDir::new();
+	let (user_secret, plain, capsule) =
+		(dir.path("user_secret"), dir.path("plain"), dir.path("capsule"));
+	fs::write(&plain, b"Testolope").unwrap();
+	
+	// Generate a user secret and use it
+	let output = rawkey(&["keygen", "--format", "text", "--bytes", "16", "--out", &user_secret]);
+	assert!(output.status.success());
+	let encoded = fs::read(&user_secret).unwrap();
+	assert_eq!(UserSecret::decode(&encoded).unwrap().len(), 16);
+	#[cfg(unix)] {
+		use std::os::unix::fs::PermissionsExt;
+		let mode = fs::metadata(&user_secret).unwrap().permissions().mode();
+		assert_eq!(mode & 0o777, 0o600);
+	}
+	let output = rawkey(&["seal", "--in", &plain, "--out", &capsule, "--secret-file", &user_secret]);
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	
+	// Use an invalid format or length
+	assert_eq!(rawkey(&["keygen", "--format", "base64"]).status.code(), Some(2));
+	assert_eq!(rawkey(&["keygen", "--format", "text", "--bytes", "8"]).status.code(), Some(1));
+	for bytes in &["0", "1", "33", "4294967295"] {
+		assert_eq!(rawkey(&["keygen", "--bytes", bytes]).status.code(), Some(1));
+	}
+}
+
+
+/// Tests reading the user secret from an environment variable and writing to stdout
+#[test]
+fn test_secret_env() {
+	let dir = TempDir::new();
+	let (plain, capsule) = (dir.path("plain"), dir.path("capsule"));
+	fs::write(&plain, b"Testolope").unwrap();
+	
+	// Seal and open the secret with a context
+	let status = Command::new(env!("CARGO_BIN_EXE_rawkey"))
+		.args(["seal", "--context", "db-master-key", "--in", &plain, "--out", &capsule])
+		.args(["--secret-env", "RAWKEY_TEST_SECRET"])
+		.env("RAWKEY_TEST_SECRET", "oGKqY-Yx8wR-HFCMv-Y9Smh-N6oZb-p7ekX-tY3c5-ExCSY-vCG6c")
+		.status().unwrap();
+	assert!(status.success());
+	let output = Command::new(env!("CARGO_BIN_EXE_rawkey"))
+		.args(["open", "--context", "db-master-key", "--in", &capsule])
+		.args(["--secret-env", "RAWKEY_TEST_SECRET"])
+		.env("RAWKEY_TEST_SECRET", "oGKqY-Yx8wR-HFCMv-Y9Smh-N6oZb-p7ekX-tY3c5-ExCSY-vCG6c")
+		.output().unwrap();
+	assert!(output.status.success());
+	assert_eq!(output.stdout, b"Testolope");
+	
+	// Use an invalid user secret
+	let output = Command::new(env!("CARGO_BIN_EXE_rawkey"))
+		.args(["open", "--context", "db-master-key", "--in", &capsule])
+		.args(["--secret-env", "RAWKEY_TEST_SECRET"])
+		.env("RAWKEY_TEST_SECRET", "Invalid")
+		.output().unwrap();
+	assert_eq!(output.status.code(), Some(1));
+	assert!(output.stdout.is_empty());
+}
+
+
+/// Tests that the user secret cannot be passed as argument
+#[test]
+fn test_invalid_usage() {
+	let dir = TempDir::new();
+	let plain = dir.path("plain");
+	fs::write(&plain, b"Testolope").unwrap();
+	
+	// Pass the secret as argument
+	let output = rawkey(&["seal", "--in", &plain, "--secret", "Testolope"]);
+	assert_eq!(output.status.code(), Some(2));
+	
+	// Pass no secret source
+	let output = rawkey(&["seal", "--in", &plain]);
+	assert_eq!(output.status.code(), Some(2));
+	
+	// Pass options that do not apply to the command
+	assert_eq!(rawkey(&["keygen", "--context", "test"]).status.code(), Some(2));
+	assert_eq!(rawkey(&["inspect", "--in", &plain, "--secret-env", "HOME"]).status.code(), Some(2));
+	let output = rawkey(&["open", "--config", "HKDF-ChaCha20Poly1305", "--secret-env", "HOME"]);
+	assert_eq!(output.status.code(), Some(2));
+	assert_eq!(rawkey(&["seal", "--bytes", "32", "--secret-env", "HOME"]).status.code(), Some(2));
+	
+	// Pass multiple secret sources
+	let output = rawkey(&["seal", "--in", &plain, "--secret-file", &plain, "--secret-env", "HOME"]);
+	assert_eq!(output.status.code(), Some(2));
+	
+	// Pass a standard stream or a closed file descriptor
+	#[cfg(unix)] {
+		for fd in &["0", "1", "2", "-1"] {
+			let output = rawkey(&["seal", "--in", &plain, "--secret-fd", fd]);
+			assert_eq!(output.status.code(), Some(2));
+		}
+		let output = rawkey(&["seal", "--in", &plain, "--secret-fd", "1000"]);
+		assert_eq!(output.status.code(), Some(1));
+	}
+}
\ No newline at end of file
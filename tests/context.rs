@@ -0,0 +1,61 @@
+use kync::{
+	Plugin,
+	plugin::{ os_default_prefix, os_default_suffix }
+};
+use std::path::PathBuf;
+
+
+const CONFIG: &[u8] = b"Blake2b-XChaChaPoly";
+const USER_SECRET: &[u8] = b"oGKqY-Yx8wR-HFCMv-Y9Smh-N6oZb-p7ekX-tY3c5-ExCSY-vCG6c";
+
+
+/// Loads the `rawkey` plugin
+fn load_plugin() -> Plugin {
+	// Create path
+	let mut path = PathBuf::new();
+	path.push("target");
+	path.push(if cfg!(debug_assertions) { "debug" } else { "release" });
+	path.push(format!("{}kync_rawkey.{}", os_default_prefix(), os_default_suffix()));
+	
+	// Load plugin
+	Plugin::load(path).unwrap()
+}
+
+
+/// Tests that capsules are bound to the context
+///
+/// Since the context is process wide, all context related tests are performed sequentially in this
+/// separate test binary
+#[test]
+fn test_context() {
+	let plugin = load_plugin();
+	
+	// Create a capsule without context and a capsule with context
+	plugin.set_context(b"").unwrap();
+	let unbound = plugin.protect(b"Testolope", CONFIG, Some(USER_SECRET)).unwrap();
+	plugin.set_context(b"db-master-key").unwrap();
+	let bound = plugin.protect(b"Testolope", CONFIG, Some(USER_SECRET)).unwrap();
+	assert_eq!(unbound[6] & 0x01, 0x00);
+	assert_eq!(bound[6] & 0x01, 0x01);
+	
+	// Recover the capsules within the same context
+	assert_eq!(plugin.recover(&bound, Some(USER_SECRET)).unwrap(), b"Testolope");
+	assert_eq!(plugin.recover(&unbound, Some(USER_SECRET)).unwrap(), b"Testolope");
+	
+	// Recover the capsules within another context
+	plugin.set_context(b"backup-key").unwrap();
+	let err = plugin.recover(&bound, Some(USER_SECRET)).unwrap_err();
+	assert!(err.to_string().contains("invalid user secret or context"));
+	assert_eq!(plugin.recover(&unbound, Some(USER_SECRET)).unwrap(), b"Testolope");
+	
+	// Recover the capsules without context
+	plugin.set_context(b"").unwrap();
+	let err = plugin.recover(&bound, Some(USER_SECRET)).unwrap_err();
+	assert!(err.to_string().contains("The capsule is bound to a context but no context is set"));
+	assert_eq!(plugin.recover(&unbound, Some(USER_SECRET)).unwrap(), b"Testolope");
+	
+	// Strip the context flag
+	let mut stripped = bound.clone();
+	stripped[6] &= !0x01;
+	assert!(plugin.recover(&stripped, Some(USER_SECRET)).is_err());
+}
\ No newline at end of file
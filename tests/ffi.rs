@@ -0,0 +1,387 @@
+mod host;
+
+use crate::host::{ Input, Sink, Slice, Source };
+use kync_rawkey::{
+	Config, Error, add_recipient, auth_info_mode, capsule_key_id, decode_user_secret, error_code,
+	generate_raw_user_secret, generate_user_secret, init, inspect, key_id, last_error_detail,
+	protect, protect_multi, protect_stream, protect_threshold, recover, recover_stream,
+	recover_threshold, remove_recipient, rewrap, set_padding, verify
+};
+use std::ptr;
+
+
+const USER_SECRET: &[u8] = b"oGKqY-Yx8wR-HFCMv-Y9Smh-N6oZb-p7ekX-tY3c5-ExCSY-vCG6c";
+
+
+/// Tests a `protect->recover` roundtrip through the C API
+#[test]
+fn test_roundtrip() {
+	let (data, auth) = (Slice::new(b"Testolope"), Slice::new(USER_SECRET));
+	let config = Slice::new(Config::Blake2bXChachaPoly.name());
+	let (protected, code) =
+		Sink::collect(|sink| protect(sink.cast(), data.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	
+	let capsule = Slice::new(&protected[0]);
+	let (recovered, code) = Sink::collect(|sink| recover(sink.cast(), capsule.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	assert_eq!(recovered, [b"Testolope"]);
+}
+
+
+/// Tests that `verify` checks the user secret without writing the secret
+#[test]
+fn test_verify() {
+	let (data, auth) = (Slice::new(b"Testolope"), Slice::new(USER_SECRET));
+	let config = Slice::new(Config::Blake2bXChachaPoly.name());
+	let (protected, code) =
+		Sink::collect(|sink| protect(sink.cast(), data.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	
+	let capsule = Slice::new(&protected[0]);
+	assert_eq!(error_code(verify(capsule.raw(), auth.raw())), 0);
+	let invalid = Slice::new(b"Invalid");
+	assert_eq!(error_code(verify(capsule.raw(), invalid.raw())), Error::WrongKey.code());
+	assert_eq!(error_code(verify(capsule.raw(), ptr::null())), Error::MissingAuth.code());
+}
+
+
+/// Tests that a host can select the user secret by the key identifier of a capsule
+#[test]
+fn test_key_id() {
+	const OTHER: &[u8] = b"7dQxW-Jm2Lp-Vc9Rt-Hk4Ns-Bz6Gy";
+	let (data, auth) = (Slice::new(b"Testolope"), Slice::new(USER_SECRET));
+	let config = Slice::new(Config::Blake2bXChachaPoly.name());
+	let (protected, code) =
+		Sink::collect(|sink| protect(sink.cast(), data.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	let capsule = Slice::new(&protected[0]);
+	let (capsule_id, code) = Sink::collect(|sink| capsule_key_id(sink.cast(), capsule.raw()));
+	assert_eq!(code, 0);
+	
+	// Select the user secret with the matching identifier
+	let auths = [Slice::new(OTHER), Slice::new(USER_SECRET)];
+	let selected = auths.iter().position(|auth| {
+		let (id, code) = Sink::collect(|sink| key_id(sink.cast(), auth.raw()));
+		assert_eq!(code, 0);
+		id == capsule_id
+	});
+	assert_eq!(selected, Some(1));
+	let (_, code) = Sink::collect(|sink| recover(sink.cast(), capsule.raw(), auths[0].raw()));
+	assert_eq!(code, Error::WrongKey.code());
+	
+	// Password capsules don't contain an identifier
+	let config = Slice::new(Config::Argon2idXChachaPoly.name());
+	let (protected, code) =
+		Sink::collect(|sink| protect(sink.cast(), data.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	let capsule = Slice::new(&protected[0]);
+	let (capsule_id, code) = Sink::collect(|sink| capsule_key_id(sink.cast(), capsule.raw()));
+	assert_eq!((capsule_id.len(), code), (0, 0));
+}
+
+
+/// Tests multi-recipient capsules through the C API
+#[test]
+fn test_multi_recipient() {
+	const BREAK_GLASS: &[u8] = b"7dQxW-Jm2Lp-Vc9Rt-Hk4Ns-Bz6Gy";
+	let data = Slice::new(b"Testolope");
+	let (auth, break_glass) = (Slice::new(USER_SECRET), Slice::new(BREAK_GLASS));
+	let config = Slice::new(Config::Blake2bXChachaPoly.name());
+	
+	// Protect the data for both recipients
+	let auths = [Slice::new(USER_SECRET), Slice::new(BREAK_GLASS)];
+	let (protected, code) = Sink::collect(|sink| {
+		protect_multi(sink.cast(), data.raw(), config.raw(), auths.as_ptr().cast(), auths.len())
+	});
+	assert_eq!(code, 0);
+	let capsule = Slice::new(&protected[0]);
+	let (recovered, code) =
+		Sink::collect(|sink| recover(sink.cast(), capsule.raw(), break_glass.raw()));
+	assert_eq!(code, 0);
+	assert_eq!(recovered, [b"Testolope"]);
+	
+	// Remove the first slot and add it again
+	let (removed, code) =
+		Sink::collect(|sink| remove_recipient(sink.cast(), capsule.raw(), break_glass.raw(), 0));
+	assert_eq!(code, 0);
+	let removed = Slice::new(&removed[0]);
+	let (_, code) = Sink::collect(|sink| recover(sink.cast(), removed.raw(), auth.raw()));
+	assert_eq!(code, Error::Authentication.code());
+	let (added, code) = Sink::collect(|sink| {
+		add_recipient(sink.cast(), removed.raw(), break_glass.raw(), auth.raw())
+	});
+	assert_eq!(code, 0);
+	let added = Slice::new(&added[0]);
+	let (recovered, code) = Sink::collect(|sink| recover(sink.cast(), added.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	assert_eq!(recovered, [b"Testolope"]);
+}
+
+
+/// Tests threshold capsules through the C API
+#[test]
+fn test_threshold() {
+	const BREAK_GLASS: &[u8] = b"7dQxW-Jm2Lp-Vc9Rt-Hk4Ns-Bz6Gy";
+	let data = Slice::new(b"Testolope");
+	let config = Slice::new(Config::Blake2bChachaPolyIetfShamir.name());
+	
+	// Protect the data so that both user secrets are required
+	let auths = [Slice::new(USER_SECRET), Slice::new(BREAK_GLASS)];
+	let (protected, code) = Sink::collect(|sink| {
+		protect_threshold(sink.cast(), data.raw(), config.raw(), 2, auths.as_ptr().cast(), 2)
+	});
+	assert_eq!(code, 0);
+	let capsule = Slice::new(&protected[0]);
+	
+	// Recover the data with both and with one user secret
+	let (recovered, code) = Sink::collect(|sink| {
+		recover_threshold(sink.cast(), capsule.raw(), auths.as_ptr().cast(), 2)
+	});
+	assert_eq!(code, 0);
+	assert_eq!(recovered, [b"Testolope"]);
+	let (_, code) = Sink::collect(|sink| {
+		recover_threshold(sink.cast(), capsule.raw(), auths.as_ptr().cast(), 1)
+	});
+	assert_eq!(code, Error::ThresholdNotMet.code());
+}
+
+
+/// Tests that stream capsules are written and recovered chunk by chunk through the C API
+#[test]
+fn test_stream() {
+	const CHUNK_LEN: usize = 64 * 1024;
+	let (data, auth) = (vec![0x2A; 2 * CHUNK_LEN + 7], Slice::new(USER_SECRET));
+	let config = Slice::new(Config::Blake2bXChachaPolyStream.name());
+	
+	// Protect the data and recover it
+	let slice = Slice::new(&data);
+	let (protected, code) =
+		Sink::collect(|sink| protect(sink.cast(), slice.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	assert_eq!(protected.len(), 4);
+	
+	let capsule = protected.concat();
+	let slice = Slice::new(&capsule);
+	let (recovered, code) = Sink::collect(|sink| recover(sink.cast(), slice.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	assert_eq!(recovered.iter().map(Vec::len).collect::<Vec<_>>(), [CHUNK_LEN, CHUNK_LEN, 7]);
+	assert_eq!(recovered.concat(), data);
+	
+	// Drop the final chunk
+	let truncated = protected[..3].concat();
+	let slice = Slice::new(&truncated);
+	let (_, code) = Sink::collect(|sink| recover(sink.cast(), slice.raw(), auth.raw()));
+	assert_eq!(code, Error::StreamTruncated.code());
+}
+
+
+/// Tests that the stream functions pull their input from a source through the C API
+#[test]
+fn test_stream_source() {
+	const CHUNK_LEN: usize = 64 * 1024;
+	let (data, auth) = (vec![0x2A; 2 * CHUNK_LEN + 7], Slice::new(USER_SECRET));
+	let config = Slice::new(Config::Blake2bXChachaPolyStream.name());
+	
+	// Protect the data from a source that returns short reads
+	let mut input = Input { data: &data, step: 1000 };
+	let mut source = Source::new(&mut input);
+	let (protected, code) =
+		Sink::collect(|sink| protect_stream(sink.cast(), source.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	assert_eq!(protected.len(), 4);
+	
+	// Recover the data from a source
+	let capsule = protected.concat();
+	let mut input = Input { data: &capsule, step: 4096 };
+	let mut source = Source::new(&mut input);
+	let (recovered, code) =
+		Sink::collect(|sink| recover_stream(sink.cast(), source.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	assert_eq!(recovered.concat(), data);
+	
+	// Use a failing source, no source or a non-stream config
+	let mut input = Input { data: &capsule, step: 0 };
+	let mut source = Source::new(&mut input);
+	let (_, code) = Sink::collect(|sink| recover_stream(sink.cast(), source.raw(), auth.raw()));
+	assert_eq!(code, Error::SourceRead.code());
+	let (_, code) = Sink::collect(|sink| recover_stream(sink.cast(), ptr::null_mut(), auth.raw()));
+	assert_eq!(code, Error::NullPointer.code());
+	
+	let config = Slice::new(Config::Blake2bXChachaPoly.name());
+	let mut input = Input { data: &data, step: 1000 };
+	let mut source = Source::new(&mut input);
+	let (protected, code) =
+		Sink::collect(|sink| protect_stream(sink.cast(), source.raw(), config.raw(), auth.raw()));
+	assert_eq!((protected.len(), code), (0, Error::InvalidConfig.code()));
+}
+
+
+/// Tests the capsule inspection through the C API
+#[test]
+fn test_inspect() {
+	const LEGACY_CAPSULE: &[u8] = b"\x14\x2e\x97\xb3\xaf\x8a\x4a\x10\x64\xaa\x67\x2b\x28\xce\x6d\x27\x39\x7e\x8e\x21\xf1\xef\x56\xa5\x61\x2c\xe2\xda\x1c\xc6\x6a\x92\x58\x7d\x12\x7f\xf1\xf5\xde\x71\xc3\x0e\x71\xbd\x7d\xd3\xed\xfb\x32\xb4\xc2\xb6\x2c";
+	let capsule = Slice::new(LEGACY_CAPSULE);
+	let (info, code) = Sink::collect(|sink| inspect(sink.cast(), capsule.raw()));
+	assert_eq!(code, 0);
+	let expected: &[&[u8]] = &[
+		b"version=0", b"config=Blake2b-ChaChaPolyIETF", b"context=false",
+		b"salt=142e97b3af8a4a1064aa672b28ce6d27", b"secret_len=9"
+	];
+	assert_eq!(info, expected);
+	
+	// Inspect a capsule for two recipients
+	let (data, config) = (Slice::new(b"Testolope"), Slice::new(Config::Blake2bXChachaPoly.name()));
+	let auths = [Slice::new(USER_SECRET), Slice::new(b"7dQxW-Jm2Lp-Vc9Rt-Hk4Ns-Bz6Gy")];
+	let (protected, code) = Sink::collect(|sink| {
+		protect_multi(sink.cast(), data.raw(), config.raw(), auths.as_ptr().cast(), auths.len())
+	});
+	assert_eq!(code, 0);
+	let capsule = Slice::new(&protected[0]);
+	let (info, code) = Sink::collect(|sink| inspect(sink.cast(), capsule.raw()));
+	assert_eq!(code, 0);
+	let expected: &[&[u8]] = &[
+		b"version=1", b"config=Blake2b-XChaChaPoly", b"context=false", b"recipients=2",
+		b"secret_len=9"
+	];
+	assert_eq!(info, expected);
+	
+	// Inspect a password capsule
+	let (config, auth) = (Slice::new(Config::Argon2idXChachaPoly.name()), Slice::new(b"passphrase"));
+	let (protected, code) =
+		Sink::collect(|sink| protect(sink.cast(), data.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	let capsule = Slice::new(&protected[0]);
+	let (info, code) = Sink::collect(|sink| inspect(sink.cast(), capsule.raw()));
+	assert_eq!(code, 0);
+	assert_eq!(info[1], b"config=Argon2id-XChaChaPoly");
+	assert_eq!(info[3], b"argon2=65536,3,4");
+	
+	// Inspect truncated capsules
+	let header = [b"RawK\x01\x01\x00", LEGACY_CAPSULE].concat();
+	for len in &[5, 8, LEGACY_CAPSULE.len() - 10] {
+		let capsule = Slice::new(&header[..*len]);
+		let (info, code) = Sink::collect(|sink| inspect(sink.cast(), capsule.raw()));
+		assert_eq!((info.len(), code), (0, Error::Truncated.code()));
+	}
+}
+
+
+/// Tests rewrapping a legacy capsule through the C API
+#[test]
+fn test_rewrap() {
+	const LEGACY_CAPSULE: &[u8] = b"\x14\x2e\x97\xb3\xaf\x8a\x4a\x10\x64\xaa\x67\x2b\x28\xce\x6d\x27\x39\x7e\x8e\x21\xf1\xef\x56\xa5\x61\x2c\xe2\xda\x1c\xc6\x6a\x92\x58\x7d\x12\x7f\xf1\xf5\xde\x71\xc3\x0e\x71\xbd\x7d\xd3\xed\xfb\x32\xb4\xc2\xb6\x2c";
+	let (capsule, old_auth) = (Slice::new(LEGACY_CAPSULE), Slice::new(USER_SECRET));
+	let new_auth = Slice::new(b"7dQxW-Jm2Lp-Vc9Rt-Hk4Ns-Bz6Gy");
+	
+	// Upgrade the legacy capsule
+	let config = Slice::new(Config::Blake2bXChachaPoly.name());
+	let (rewrapped, code) = Sink::collect(|sink| {
+		rewrap(sink.cast(), capsule.raw(), old_auth.raw(), new_auth.raw(), config.raw())
+	});
+	assert_eq!(code, 0);
+	assert_eq!(rewrapped[0][5], Config::Blake2bXChachaPoly.id());
+	
+	// Rewrap it again but keep the config
+	let capsule = Slice::new(&rewrapped[0]);
+	let (rewrapped, code) = Sink::collect(|sink| {
+		rewrap(sink.cast(), capsule.raw(), new_auth.raw(), old_auth.raw(), ptr::null())
+	});
+	assert_eq!(code, 0);
+	assert_eq!(rewrapped[0][5], Config::Blake2bXChachaPoly.id());
+	
+	let capsule = Slice::new(&rewrapped[0]);
+	let (recovered, code) =
+		Sink::collect(|sink| recover(sink.cast(), capsule.raw(), old_auth.raw()));
+	assert_eq!(code, 0);
+	assert_eq!(recovered, [b"Testolope"]);
+}
+
+
+/// Tests that the auth mode distinguishes key and password configs
+#[test]
+fn test_auth_info_mode() {
+	for config in Config::ALL {
+		let (name, mut mode) = (Slice::new(config.name()), 0xFF);
+		assert_eq!(error_code(auth_info_mode(&mut mode, name.raw())), 0);
+		assert_eq!(mode, config.is_password() as u8);
+	}
+	
+	let (name, mut mode) = (Slice::new(b"Unknown"), 0xFF);
+	assert_eq!(error_code(auth_info_mode(&mut mode, name.raw())), Error::InvalidConfig.code());
+}
+
+/// Tests the validation of the process wide padding
+///
+/// The padding is only set to the default, so the other tests are not affected.
+#[test]
+fn test_set_padding() {
+	assert_eq!(error_code(set_padding(3, 0)), Error::InvalidPadding.code());
+	assert_eq!(error_code(set_padding(2, 0)), Error::InvalidPadding.code());
+	assert_eq!(error_code(set_padding(2, 1024 * 1024 + 1)), Error::InvalidPadding.code());
+	assert_eq!(error_code(set_padding(2, u64::MAX)), Error::InvalidPadding.code());
+	assert_eq!(error_code(set_padding(0, 0)), 0);
+}
+
+/// Tests the user secret generation and decoding
+#[test]
+fn test_generate_user_secret() {
+	// Generate and decode an encoded user secret
+	let (encoded, code) = Sink::collect(|sink| generate_user_secret(sink.cast(), 256));
+	assert_eq!(code, 0);
+	let encoded_slice = Slice::new(&encoded[0]);
+	let (raw, code) = Sink::collect(|sink| decode_user_secret(sink.cast(), encoded_slice.raw()));
+	assert_eq!(code, 0);
+	assert_eq!(raw[0].len(), 32);
+	
+	// Generate a raw user secret
+	let (raw, code) = Sink::collect(|sink| generate_raw_user_secret(sink.cast(), 128));
+	assert_eq!(code, 0);
+	assert_eq!(raw[0].len(), 16);
+	
+	// Use an invalid strength or a mistyped user secret
+	let (_, code) = Sink::collect(|sink| generate_user_secret(sink.cast(), 100));
+	assert_eq!(code, Error::InvalidSecretStrength.code());
+	let mut typo = encoded[0].clone();
+	typo[0] = if typo[0] == b'x' { b'y' } else { b'x' };
+	let typo = Slice::new(&typo);
+	let (_, code) = Sink::collect(|sink| decode_user_secret(sink.cast(), typo.raw()));
+	assert_eq!(code, Error::InvalidUserSecret.code());
+}
+
+/// Tests the thread-local last error detail
+#[test]
+fn test_last_error_detail() {
+	// Trigger an error without underlying cause
+	assert_eq!(error_code(init(0xFFFF, 0)), Error::UnsupportedApi.code());
+	let (detail, code) = Sink::collect(|sink| last_error_detail(sink.cast()));
+	assert_eq!(code, 0);
+	assert_eq!(detail, [b"Unsupported API version"]);
+	
+	// Trigger an error with an underlying cause (Blake2b only supports keys up to 64 bytes)
+	let too_long = [USER_SECRET, b"-Xq7bT-Lm3Pz"].concat();
+	let (data, auth) = (Slice::new(b"Testolope"), Slice::new(&too_long));
+	let config = Slice::new(Config::Blake2bXChachaPoly.name());
+	let (_, code) = Sink::collect(|sink| protect(sink.cast(), data.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, Error::Kdf.code());
+	let (detail, _) = Sink::collect(|sink| last_error_detail(sink.cast()));
+	let detail = String::from_utf8(detail[0].clone()).unwrap();
+	assert!(detail.starts_with("The KDF failed to derive a key ("), "{}", detail);
+	
+	// Trigger a key identifier mismatch (which must not be retried as legacy capsule)
+	let (data, auth) = (Slice::new(b"Testolope"), Slice::new(USER_SECRET));
+	let (capsule, code) =
+		Sink::collect(|sink| protect(sink.cast(), data.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	let (capsule, auth) = (Slice::new(&capsule[0]), Slice::new(b"7dQxW-Jm2Lp-Vc9Rt-Hk4Ns-Bz6Gy"));
+	let (_, code) = Sink::collect(|sink| recover(sink.cast(), capsule.raw(), auth.raw()));
+	assert_eq!(code, Error::WrongKey.code());
+	let (detail, _) = Sink::collect(|sink| last_error_detail(sink.cast()));
+	let detail = String::from_utf8(detail[0].clone()).unwrap();
+	assert!(detail.ends_with("(The key identifier does not match)"), "{}", detail);
+	
+	// Ensure that the detail is thread-local
+	let (detail, _) = std::thread::spawn(|| Sink::collect(|sink| last_error_detail(sink.cast())))
+		.join().unwrap();
+	assert!(detail.is_empty());
+}
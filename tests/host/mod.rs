@@ -0,0 +1,90 @@
+//! A minimal Rust host for the C API
+#![allow(unused)]
+
+use kync_rawkey::error_code;
+use std::{ ptr, slice, ffi::c_void, os::raw::c_char };
+
+
+/// A C-compatible slice (see `slice_t`)
+#[repr(C)]
+pub struct Slice {
+	ptr: *const u8,
+	len: usize
+}
+impl Slice {
+	/// Creates a slice over `data`
+	pub fn new(data: &[u8]) -> Self {
+		Self { ptr: data.as_ptr(), len: data.len() }
+	}
+	/// A pointer to this slice that can be passed to the C API
+	pub fn raw<T>(&self) -> *const T {
+		(self as *const Self).cast()
+	}
+}
+
+
+/// A C-compatible sink (see `write_t`) that collects all segments
+#[repr(C)]
+pub struct Sink {
+	pub handle: *mut c_void,
+	pub write: Option<unsafe extern "C" fn(*mut c_void, *const Slice) -> *const c_char>
+}
+impl Sink {
+	/// Creates a sink that appends all segments to `segments`
+	pub fn new(segments: &mut Vec<Vec<u8>>) -> Self {
+		unsafe extern "C" fn write(handle: *mut c_void, data: *const Slice) -> *const c_char {
+			let (segments, data) = unsafe{ (&mut *handle.cast::<Vec<Vec<u8>>>(), &*data) };
+			segments.push(unsafe{ slice::from_raw_parts(data.ptr, data.len) }.to_vec());
+			ptr::null()
+		}
+		Self { handle: (segments as *mut Vec<Vec<u8>>).cast(), write: Some(write) }
+	}
+	/// Calls `f` with a sink and returns the collected segments together with the error code
+	pub fn collect(f: impl FnOnce(*mut Sink) -> *const c_char) -> (Vec<Vec<u8>>, u32) {
+		let mut segments = Vec::new();
+		let mut sink = Sink::new(&mut segments);
+		let code = error_code(f(&mut sink));
+		(segments, code)
+	}
+}
+
+
+/// The input of a `Source`
+pub struct Input<'a> {
+	/// The remaining data
+	pub data: &'a[u8],
+	/// The maximum amount of bytes per read (`0` simulates a failing source)
+	pub step: usize
+}
+
+
+/// A C-compatible source (see `read_t`) that reads from an `Input`
+#[repr(C)]
+pub struct Source {
+	pub handle: *mut c_void,
+	pub read: Option<unsafe extern "C" fn(*mut c_void, *mut u8, usize, *mut usize) -> *const c_char>
+}
+impl Source {
+	/// Creates a source that reads from `input`
+	pub fn new(input: &mut Input) -> Self {
+		unsafe extern "C" fn read(handle: *mut c_void, buf: *mut u8, buf_len: usize,
+			read: *mut usize) -> *const c_char
+		{
+			let input = unsafe{ &mut *handle.cast::<Input>() };
+			if input.step == 0 {
+				return b"The source failed\0".as_ptr().cast()
+			}
+			
+			let len = buf_len.min(input.step).min(input.data.len());
+			unsafe{ slice::from_raw_parts_mut(buf, len) }.copy_from_slice(&input.data[..len]);
+			input.data = &input.data[len..];
+			unsafe{ *read = len };
+			ptr::null()
+		}
+		Self { handle: (input as *mut Input).cast(), read: Some(read) }
+	}
+	/// A pointer to this source that can be passed to the C API
+	pub fn raw<T>(&mut self) -> *mut T {
+		(self as *mut Self).cast()
+	}
+}
\ No newline at end of file
@@ -0,0 +1,60 @@
+mod host;
+
+use crate::host::{ Sink, Slice };
+use kync_rawkey::{ Config, Error, error_code, init, protect, recover, set_log_sink };
+use std::ptr;
+
+
+const USER_SECRET: &[u8] = b"oGKqY-Yx8wR-HFCMv-Y9Smh-N6oZb-p7ekX-tY3c5-ExCSY-vCG6c";
+
+
+/// Tests the host provided log sink and the log levels
+///
+/// Since the log sink and level are process wide, this test is performed in a separate test binary
+#[test]
+fn test_log_sink() {
+	let mut lines = Vec::new();
+	let mut sink = Sink::new(&mut lines);
+	assert_eq!(error_code(set_log_sink(&mut sink as *mut Sink as _)), 0);
+	
+	// Log some info and debug lines
+	assert_eq!(error_code(init(0x01_00, 4)), 0);
+	let (data, auth) = (Slice::new(b"Testolope"), Slice::new(USER_SECRET));
+	let config = Slice::new(Config::Blake2bXChachaPoly.name());
+	let (_, code) = Sink::collect(|sink| protect(sink.cast(), data.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	
+	// Log an error but suppress the info line
+	assert_eq!(error_code(init(0x01_00, 1)), 0);
+	let too_long = [USER_SECRET, b"-Xq7bT-Lm3Pz"].concat();
+	let auth = Slice::new(&too_long);
+	let (_, code) = Sink::collect(|sink| protect(sink.cast(), data.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, Error::Kdf.code());
+	
+	// A damaged capsule with a valid header is not retried as legacy capsule
+	let auth = Slice::new(USER_SECRET);
+	let (mut capsule, _) =
+		Sink::collect(|sink| protect(sink.cast(), data.raw(), config.raw(), auth.raw()));
+	*capsule[0].last_mut().unwrap() ^= 0x01;
+	let capsule = Slice::new(&capsule[0]);
+	let (_, code) = Sink::collect(|sink| recover(sink.cast(), capsule.raw(), auth.raw()));
+	assert_eq!(code, Error::Authentication.code());
+	
+	// Restore stderr and validate the log lines
+	assert_eq!(error_code(set_log_sink(ptr::null())), 0);
+	let lines: Vec<String> = lines.into_iter().map(|l| String::from_utf8(l).unwrap()).collect();
+	assert_eq!(lines.len(), 4, "{:?}", lines);
+	assert_eq!(lines[0], "[info] Initialized rawkey with API version 0x0100");
+	assert_eq!(lines[1], "[debug] Protecting 9 bytes using Blake2bXChachaPoly");
+	assert!(lines[2].starts_with("[error] "), "{:?}", lines);
+	assert!(lines[3].starts_with("[error] "), "{:?}", lines);
+}
+
+
+/// Tests that an invalid log sink is rejected
+#[test]
+fn test_invalid_log_sink() {
+	let sink = Sink { handle: ptr::null_mut(), write: None };
+	let code = error_code(set_log_sink(&sink as *const Sink as _));
+	assert_eq!(code, Error::NullPointer.code());
+}
\ No newline at end of file
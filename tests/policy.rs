@@ -0,0 +1,32 @@
+mod host;
+
+use crate::host::{ Sink, Slice };
+use kync_rawkey::{ Config, Error, error_code, last_error_detail, protect, set_auth_policy };
+
+
+/// Tests the process wide auth policy
+///
+/// Since the policy is process wide, this test is performed in a separate test binary
+#[test]
+fn test_set_auth_policy() {
+	let (data, auth) = (Slice::new(b"Testolope"), Slice::new(b"weak"));
+	let config = Slice::new(Config::Blake2bXChachaPoly.name());
+	
+	// Reject the weak user secret
+	let (_, code) = Sink::collect(|sink| protect(sink.cast(), data.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, Error::WeakAuth.code());
+	let (detail, _) = Sink::collect(|sink| last_error_detail(sink.cast()));
+	let detail = String::from_utf8(detail[0].clone()).unwrap();
+	assert!(detail.ends_with("(The user secret is shorter than 16 bytes)"), "{}", detail);
+	
+	// Disable the policy
+	assert_eq!(error_code(set_auth_policy(0, 0)), 0);
+	let (_, code) = Sink::collect(|sink| protect(sink.cast(), data.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, 0);
+	
+	// Tighten the policy
+	assert_eq!(error_code(set_auth_policy(64, 1)), 0);
+	let auth = Slice::new(b"oGKqY-Yx8wR-HFCMv-Y9Smh-N6oZb-p7ekX-tY3c5-ExCSY-vCG6c");
+	let (_, code) = Sink::collect(|sink| protect(sink.cast(), data.raw(), config.raw(), auth.raw()));
+	assert_eq!(code, Error::WeakAuth.code());
+}
\ No newline at end of file
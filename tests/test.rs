@@ -2,11 +2,15 @@ use kync::{
 	Plugin,
 	plugin::{ os_default_prefix, os_default_suffix }
 };
-use std::{ u128, path::PathBuf };
+use std::path::PathBuf;
 use crypto_api_osrandom::OsRandom;
 
 
-const CONFIG: &[u8] = b"Blake2b-ChaChaPolyIETF";
+const CONFIGS: &[&[u8]] = &[
+	b"Blake2b-ChaChaPolyIETF", b"Blake2b-XChaChaPoly", b"Blake2b-AES256GCMSIV",
+	b"Blake2b-XChaChaPoly-Committing", b"HKDF-SHA512-ChaChaPolyIETF", b"BLAKE3-ChaChaPolyIETF",
+	b"Argon2id-XChaChaPoly", b"Blake2b-ChaChaPolyIETF-Shamir", b"Blake2b-XChaChaPoly-STREAM"
+];
 
 
 /// Loads the `rawkey` plugin
@@ -54,6 +58,11 @@ impl Random {
 		const MAX: u128 = 63;
 		Self::num(MAX) as usize + 1
 	}
+	/// Creates a uniform random user secret length in `[16, 64]`
+	pub fn auth_len() -> usize {
+		const MAX: u128 = 49;
+		Self::num(MAX) as usize + 16
+	}
 }
 
 
@@ -63,14 +72,15 @@ impl RandomizedTestVector {
 	/// Run a randomized tests
 	pub fn test(&self, plugin: &Plugin) {
 		// Generate random password and key and select a random preset
-		let (secret, auth) = (Random::vec(Random::len()), Random::vec(Random::len()));
+		let (secret, auth) = (Random::vec(Random::len()), Random::vec(Random::auth_len()));
+		let config = CONFIGS[Random::num(CONFIGS.len() as u128) as usize];
 		
 		// Seal the key
 		println!(
-			"*> Performing `seal->open`-test with a {} byte secret and {} byte auth data...",
-			secret.len(), auth.len()
+			"*> Performing `seal->open`-test with a {} byte secret and {} byte auth data using {}...",
+			secret.len(), auth.len(), String::from_utf8_lossy(config)
 		);
-		let protected = plugin.protect(&secret, CONFIG, Some(&auth)).unwrap();
+		let protected = plugin.protect(&secret, config, Some(&auth)).unwrap();
 		
 		// Open capsule and compare keys
 		let recovered = plugin.recover(&protected, Some(&auth)).unwrap();
@@ -89,6 +99,39 @@ fn test() {
 }
 
 
+/// Tests that all configs are advertised
+#[test]
+fn test_configs() {
+	let plugin = load_plugin();
+	let configs = plugin.configs().unwrap();
+	assert_eq!(configs, CONFIGS);
+}
+
+
+/// Tests that capsules are self-describing and that unknown versions are rejected
+#[test]
+fn test_header() {
+	const USER_SECRET: &[u8] = b"oGKqY-Yx8wR-HFCMv-Y9Smh-N6oZb-p7ekX-tY3c5-ExCSY-vCG6c";
+	
+	let plugin = load_plugin();
+	for (config_id, config) in CONFIGS.iter().enumerate() {
+		// Protect a secret and validate the header (all key configs store a key identifier)
+		let protected = plugin.protect(b"Testolope", config, Some(USER_SECRET)).unwrap();
+		let flags = match *config {
+			b"Argon2id-XChaChaPoly" | b"Blake2b-ChaChaPolyIETF-Shamir" => 0x00,
+			_ => 0x04
+		};
+		assert_eq!(&protected[..7], &[b'R', b'a', b'w', b'K', 0x01, config_id as u8 + 1, flags]);
+		
+		// Bump the version
+		let mut invalid = protected.clone();
+		invalid[4] = 0x02;
+		let err = plugin.recover(&invalid, Some(USER_SECRET)).unwrap_err();
+		assert!(err.to_string().contains("Unsupported capsule format version"));
+	}
+}
+
+
 /// Tests a predefined capsule
 #[test]
 fn test_predefined() {
@@ -99,4 +142,73 @@ fn test_predefined() {
 	let plugin = load_plugin();
 	let key = plugin.recover(CAPSULE, Some(USER_SECRET)).unwrap();
 	assert_eq!(key, KEY);
+}
+
+
+/// Predefined self-describing capsules for each config that are bound to their header, salt, config
+/// and the plugin UID (secret: `Testolope`, user secret: see `test_predefined`)
+const BOUND_CAPSULES: &[&[u8]] = &[
+	b"\x52\x61\x77\x4b\x01\x01\x00\x4a\x34\x17\x09\x68\x78\x9d\xf0\x7f\xe6\xe9\x2a\x7b\x6d\x97\x02\xa4\x2e\x43\xe7\x71\x7c\x24\x63\xbb\x15\x4d\x71\x78\xc1\x8e\x93\x90\x24\x01\x60\x02\xe0\x69\xdf\xf7\x6e\x23\x75\xa4\x0d\x01\x7e\x4d\x34\xb3\x9e\x74",
+	b"\x52\x61\x77\x4b\x01\x02\x00\x20\x98\xaa\x5f\xa9\x25\x08\x20\xee\xb1\x18\x64\xa1\x77\xb8\x8c\x51\x54\xbf\xa0\x12\x07\xd6\x9f\x26\x1d\x2b\x33\x2e\x45\x24\x7c\x46\xfc\xa3\x2a\x86\xde\xbb\x81\xf2\x98\x8e\xdf\x31\x06\x73\x27\x2f\x64\xbc\xb2\x54\x72\x10\xa0\x9c\x60\xdd\xb7\x98\x22\xfc\x22\x88",
+	b"\x52\x61\x77\x4b\x01\x03\x00\x6c\x4a\xa7\xb1\x21\x68\xe7\xc1\x12\x94\xa7\x90\xee\x44\xbc\xb4\x8c\xf7\x40\xe5\x32\x33\x1d\x1a\x63\x05\xe3\x51\xab\x29\x62\x4a\x27\xd0\xd7\xa2\x48\x78\xe4\x62\xd3\xca\x32\x4f\x81\x0f\xba\xcd\xa1\xe6\x98\x81\x86",
+	b"\x52\x61\x77\x4b\x01\x04\x00\x4f\x17\xf0\xb7\x85\x2d\xf4\x69\xee\x3c\xf3\x9a\xc4\x78\xf3\xf1\x48\x55\x8b\x94\xc0\xc4\x69\xb6\x62\x24\x09\xcd\x8c\x7b\x46\x79\x4b\x8a\xf3\x50\xf0\x5d\x76\xad\x6f\x7f\x8f\xfa\x8c\x04\xc5\xd5\xf9\xc8\x99\x65\x39\x52\x7d\xa6\x40\x60\x17\xc6\x89\x16\x9e\x66\x23\xda\x57\x56\xf5\x6a\x79\xb2\x23\xef\x90\x66\xe6\x77\xde\xd8\x95\x88\x14\x84\x96\x7a\xa1\xa7\xbe\x35\x43\xdd\x1f\xd5\x00\xf5\xeb",
+	b"\x52\x61\x77\x4b\x01\x05\x00\x99\x5a\xb4\x28\x39\xa5\xa4\x7e\xa0\x61\x7b\x4b\xa2\x3c\x2d\xcf\x45\xff\x7d\x2f\x94\x83\x5b\x1f\xf9\xdf\x4c\x90\x27\x60\x49\x96\xe5\x40\x57\xd3\x47\x42\x0a\x7c\x59\xae\x91\xce\x6e\xee\x73\x0b\x7f\x18\x9c\xe2\x95",
+	b"\x52\x61\x77\x4b\x01\x06\x00\x59\x6e\x94\x43\x26\x12\x03\xa4\xa1\x29\x28\x6f\xee\x1a\x09\x5c\xb2\x49\xf5\x0a\x4e\x4f\xf5\x77\x2e\xa8\x81\x3a\x17\xc1\x01\xff\xd2\xb1\x2c\x1c\x3f\x3c\x3f\xe4\x33\x43\xf7\x46\x64\xfa\xdc\x6b\x9d\x51\xf0\x69\x3b"
+];
+
+
+/// Tests the predefined bound capsules
+#[test]
+fn test_predefined_bound() {
+	const KEY: &[u8] = b"Testolope";
+	const USER_SECRET: &[u8] = b"oGKqY-Yx8wR-HFCMv-Y9Smh-N6oZb-p7ekX-tY3c5-ExCSY-vCG6c";
+	
+	let plugin = load_plugin();
+	for capsule in BOUND_CAPSULES {
+		let key = plugin.recover(capsule, Some(USER_SECRET)).unwrap();
+		assert_eq!(key, KEY);
+	}
+}
+
+
+/// Tests that tampering with any header or salt byte of a bound capsule is detected
+#[test]
+fn test_tampered_header() {
+	const USER_SECRET: &[u8] = b"oGKqY-Yx8wR-HFCMv-Y9Smh-N6oZb-p7ekX-tY3c5-ExCSY-vCG6c";
+	
+	let plugin = load_plugin();
+	for capsule in BOUND_CAPSULES {
+		// Flip every bit in the header and salt
+		for pos in 0 .. 7 + 16 {
+			for bit in 0 .. 8 {
+				let mut tampered = capsule.to_vec();
+				tampered[pos] ^= 1 << bit;
+				assert!(plugin.recover(&tampered, Some(USER_SECRET)).is_err());
+			}
+		}
+	}
+}
+
+
+/// A predefined password capsule with the Argon2id parameters `m_cost = 8192`, `t_cost = 1` and
+/// `p_cost = 1` (secret: `Testolope`, user secret: see `test_predefined`)
+const PASSWORD_CAPSULE: &[u8] = b"\x52\x61\x77\x4b\x01\x07\x00\x00\x00\x20\x00\x00\x00\x00\x01\x00\x00\x00\x01\x09\xd5\xfd\x17\x06\xdd\xfc\xa5\x11\xc4\x35\x2c\xf2\x8e\xc3\xe2\xd8\xcf\x7f\xe4\x08\x3a\x0c\xf0\x5a\xa2\xe1\x2a\xa2\xab\x62\x0b\x57\x8d\x36\xbf\x1e\x51\x75\x50\x58\x45\x53\x15\xc9\xcc\xe2\x9c\x71\x0e\x1f\xa4\x35\xef\x0c\x7b\x98\x78\x3f\xeb\x74\xd7\xdc\xc8\x33";
+
+
+/// Tests the predefined password capsule and that out-of-bounds Argon2id parameters are rejected
+#[test]
+fn test_predefined_password() {
+	const USER_SECRET: &[u8] = b"oGKqY-Yx8wR-HFCMv-Y9Smh-N6oZb-p7ekX-tY3c5-ExCSY-vCG6c";
+	
+	let plugin = load_plugin();
+	let key = plugin.recover(PASSWORD_CAPSULE, Some(USER_SECRET)).unwrap();
+	assert_eq!(key, b"Testolope");
+	
+	// Use a huge memory cost, a huge amount of passes or a huge parallelism
+	for pos in &[7, 11, 15] {
+		let mut tampered = PASSWORD_CAPSULE.to_vec();
+		tampered[*pos] = 0xFF;
+		let err = plugin.recover(&tampered, Some(USER_SECRET)).unwrap_err();
+		assert!(err.to_string().contains("The KDF parameters are out of bounds"));
+	}
 }
\ No newline at end of file